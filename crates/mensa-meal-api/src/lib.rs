@@ -1,8 +1,10 @@
 
 pub mod raw;
 mod processed;
+mod render;
 
 
 pub use processed::*;
+pub use render::{Format, Lang, PriceGroup};
 
 
@@ -1,6 +1,6 @@
 use std::{borrow::Cow, collections::{HashMap, HashSet}, future::Future};
 
-use chrono::NaiveDate;
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
 
 use super::raw;
@@ -11,6 +11,11 @@ pub struct MealPlans {
 }
 
 impl MealPlans {
+    /// used when no language is given
+    pub const DEFAULT_LANG: &'static str = "en";
+    /// the languages upstream has plans in
+    pub const LANGS: &'static [&'static str] = &["de", "en"];
+
     pub fn get(&self, mensa: &str, lang: Option<&str>) -> Option<&MealPlan> {
         self.mensas.get(&Self::key(mensa, lang))
     }
@@ -23,27 +28,55 @@ impl MealPlans {
     }
 
     pub fn key(mensa: &str, lang: Option<&str>) -> String {
-        const DEFAULT_LANG: &str = "en";
-        format!("{};{mensa}", lang.unwrap_or(DEFAULT_LANG))
+        format!("{};{mensa}", lang.unwrap_or(Self::DEFAULT_LANG))
     }
 
     pub fn mensas(&self) -> impl Iterator<Item = (&str, &str)> {
         self.mensas.keys().flat_map(|v| v.split_once(";"))
     }
+
+    pub fn len(&self) -> usize { self.mensas.len() }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&MealPlan) -> bool) {
+        self.mensas.retain(|_, v| f(v))
+    }
+
+    /// like [`Self::retain`], but by (mensa, lang)
+    pub fn retain_keys(&mut self, mut f: impl FnMut(&str, &str) -> bool) {
+        self.mensas.retain(|k, _| k.split_once(';').is_some_and(|(lang, mensa)| f(mensa, lang)))
+    }
+
+    pub fn is_empty(&self) -> bool { self.mensas.is_empty() }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct MealPlan {
     /// is always sorted
     days: Vec<MealDay>,
     mensa_name: String,
+    /// `None` for plans from before this was tracked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fetched_at: Option<DateTime<Utc>>,
+    /// the upstream url the plan came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
 }
 
 impl MealPlan {
     pub fn new(mensa_name: String) -> Self {
-        Self { mensa_name, days: Vec::new() }
+        Self { mensa_name, days: Vec::new(), fetched_at: None, source: None }
+    }
+
+    pub fn set_fetched(&mut self, source: &str, at: DateTime<Utc>) {
+        self.source = Some(source.into());
+        self.fetched_at = Some(at);
     }
 
+    pub fn fetched_at(&self) -> Option<DateTime<Utc>> { self.fetched_at }
+
+    pub fn source(&self) -> Option<&str> { self.source.as_deref() }
+
     #[inline]
     fn assert_sorted(&self) {
         debug_assert!(self.days
@@ -95,6 +128,29 @@ impl MealPlan {
     pub fn days(&self) -> std::slice::Iter<'_, MealDay> {
         self.days.iter()
     }
+
+    /// see [`MealDay::content_hash`]
+    pub fn content_hash(&self) -> u64 {
+        self.days.iter().fold(fnv1a(FNV_OFFSET, self.mensa_name.as_bytes()), |hash, day| {
+            fnv1a(hash, &day.content_hash().to_le_bytes())
+        })
+    }
+
+    /// first and last day upstream returned meals for
+    pub fn date_range(&self) -> Option<(NaiveDate, NaiveDate)> {
+        Some((self.days.first()?.date, self.days.last()?.date))
+    }
+
+    /// what can be said about `day` from the upstream data alone
+    pub fn day_state(&self, day: &NaiveDate) -> DayState {
+        match self.date_range() {
+            _ if self.get_day_internal(day).is_some() => DayState::Open,
+            Some((first, last)) if first <= *day && *day <= last =>
+                DayState::Closed { reason: ClosureReason::NoMeals },
+            Some((_, last)) if *day > last => DayState::NotYetPublished,
+            _ => DayState::Unknown,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -125,11 +181,13 @@ impl TryFrom<raw::ApiResult> for MealPlan {
                     })() } else { None },
                     info: MealInfo::parse(&meal.kennzeichnungen),
                     id: meal.attributes.artikel_id,
+                    md5: meal.md5,
                 });
             }
 
             Ok(MealDay {
                 date: NaiveDate::parse_from_str(&v.tag.datum_iso, "%Y-%m-%d")?,
+                state: DayState::Open,
                 categories,
             })
         }).collect::<Result<Vec<MealDay>, MealPlanParseError>>()?;
@@ -139,17 +197,148 @@ impl TryFrom<raw::ApiResult> for MealPlan {
         Ok(Self {
             days,
             mensa_name: value.mensaname,
+            fetched_at: None,
+            source: None,
         })
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct MealDay {
     pub date: chrono::NaiveDate,
+    /// days from upstream are always open
+    #[serde(default)]
+    pub state: DayState,
     pub categories: HashMap<String, Vec<MensaMeal>>,
 }
 
+/// why a day does (not) have meals
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DayState {
+    #[default]
+    Open,
+    Closed { reason: ClosureReason },
+    /// after the last day upstream has published
+    NotYetPublished,
+    /// before the published range or no plan at all
+    Unknown,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum ClosureReason {
+    /// public holiday (with its name)
+    Holiday(String),
+    /// configured closure (semester break, renovation, ...)
+    Closure(Option<String>),
+    /// not a regular opening day (weekends)
+    RegularHours,
+    /// upstream has no meals for a day within the published range
+    NoMeals,
+}
+
+impl ClosureReason {
+    /// short machine readable code, the text is in [`Self::detail`]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClosureReason::Holiday(_) => "holiday",
+            ClosureReason::Closure(_) => "closure",
+            ClosureReason::RegularHours => "regular_hours",
+            ClosureReason::NoMeals => "no_meals",
+        }
+    }
+
+    /// the name of the holiday or the text of the closure
+    pub fn detail(&self) -> Option<&str> {
+        match self {
+            ClosureReason::Holiday(v) | ClosureReason::Closure(Some(v)) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl MealDay {
+    /// a day without meals
+    pub fn empty(date: NaiveDate, state: DayState) -> Self {
+        Self { date, state, categories: HashMap::new() }
+    }
+
+    /// a copy containing only the meals matching `filter`,
+    /// categories without any matching meals are dropped
+    pub fn filter(&self, filter: &MealFilter) -> MealDay {
+        MealDay {
+            date: self.date,
+            state: self.state.clone(),
+            categories: self.categories.iter().filter_map(|(k, v)| {
+                let meals: Vec<_> = v.iter()
+                    .filter(|v| filter.matches(v))
+                    .cloned()
+                .collect();
+                (!meals.is_empty()).then(|| (k.clone(), meals))
+            }).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.categories.values().all(Vec::is_empty)
+    }
+
+    /// changes whenever the meals do, stable across runs,
+    /// based on upstream's md5 of every meal
+    pub fn content_hash(&self) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET, self.date.to_string().as_bytes());
+        hash = fnv1a(hash, format!("{:?}", self.state).as_bytes());
+
+        let mut categories: Vec<_> = self.categories.iter().collect();
+        categories.sort_by_key(|(k, _)| *k);
+        for (category, meals) in categories {
+            hash = fnv1a(hash, category.as_bytes());
+            for meal in meals {
+                hash = meal.hash_into(hash);
+            }
+        }
+        hash
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// 64 bit FNV-1a, unlike `DefaultHasher` its output never changes
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    // separates fields, so ("ab", "c") and ("a", "bc") differ
+    (hash ^ 0xff).wrapping_mul(0x100000001b3)
+}
+
+/// selects meals by their [`MealInfo`]
+#[derive(Debug, Default, Clone)]
+pub struct MealFilter {
+    /// meals need all of these
+    pub attributes: HashSet<MealAttribute>,
+    /// meals may not contain any of these
+    pub allergens: HashSet<MealAllergen>,
+}
+
+impl MealFilter {
+    pub fn is_empty(&self) -> bool {
+        self.attributes.is_empty() && self.allergens.is_empty()
+    }
+
+    pub fn matches(&self, meal: &MensaMeal) -> bool {
+        self.attributes.is_subset(&meal.info.attributes)
+            && self.allergens.is_disjoint(&meal.info.allergens)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct MensaMeal {
     // should prob. be title_orig or title_clean
     title: String,
@@ -159,9 +348,35 @@ pub struct MensaMeal {
     info: MealInfo,
     // use article id or sth. to uniqely identify
     id: String,
+    /// upstream's hash of the meal, missing on days stored before it was kept
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    md5: String,
+}
+
+impl MensaMeal {
+    pub fn title(&self) -> &str { &self.title }
+
+    pub fn description(&self) -> Option<&str> { self.description.as_deref() }
+
+    pub fn price(&self) -> Option<&MealPrice> { self.price.as_ref() }
+
+    pub fn info(&self) -> &MealInfo { &self.info }
+
+    pub fn id(&self) -> &str { &self.id }
+
+    fn hash_into(&self, hash: u64) -> u64 {
+        if !self.md5.is_empty() { return fnv1a(hash, self.md5.as_bytes()) }
+
+        // stored without the md5
+        let hash = fnv1a(hash, self.id.as_bytes());
+        let hash = fnv1a(hash, self.title.as_bytes());
+        let hash = fnv1a(hash, self.description.as_deref().unwrap_or_default().as_bytes());
+        fnv1a(hash, format!("{:?}", self.price).as_bytes())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct MealInfo {
     env_rating: MealEnvRating,
     addatives: HashSet<MealAddative>,
@@ -171,6 +386,14 @@ pub struct MealInfo {
 
 
 impl MealInfo {
+    pub fn env_rating(&self) -> &MealEnvRating { &self.env_rating }
+
+    pub fn addatives(&self) -> &HashSet<MealAddative> { &self.addatives }
+
+    pub fn allergens(&self) -> &HashSet<MealAllergen> { &self.allergens }
+
+    pub fn attributes(&self) -> &HashSet<MealAttribute> { &self.attributes }
+
     fn parse(desc: &str) -> Self {
         #[derive(Debug)]
         enum InfoItem {
@@ -273,6 +496,7 @@ impl MealInfo {
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum MealAttribute {
     Vegan,
@@ -285,6 +509,7 @@ pub enum MealAttribute {
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum MealAllergen {
     Gluten,             // 21
@@ -320,6 +545,7 @@ pub enum MealAllergen {
 }
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum MealAddative {
     Pork,               // 2
@@ -341,23 +567,46 @@ pub enum MealAddative {
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct MealEnvRating {
     health: Option<Rating>,
     co2: Option<Rating>,
     h2o: Option<Rating>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+impl MealEnvRating {
+    pub fn health(&self) -> Option<Rating> { self.health }
+
+    pub fn co2(&self) -> Option<Rating> { self.co2 }
+
+    pub fn h2o(&self) -> Option<Rating> { self.h2o }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 #[serde(rename_all = "snake_case")]
 pub enum Rating { Red, Yellow, Green }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct MealPrice {
+    // in euro with a comma, like `3,45`
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     students: Price,
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     servants: Price,
+    #[cfg_attr(feature = "openapi", schema(value_type = String))]
     guests: Price,
 }
 
+impl MealPrice {
+    pub fn students(&self) -> &Price { &self.students }
+
+    pub fn servants(&self) -> &Price { &self.servants }
+
+    pub fn guests(&self) -> &Price { &self.guests }
+}
+
 #[derive(Debug, Clone)]
 pub struct Price {
     eur: u32,
@@ -368,7 +617,14 @@ impl Serialize for Price {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where S: serde::Serializer
     {
-        serializer.serialize_str(&format!("{},{}", self.eur, self.cent))
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// like upstream, `3,45`
+impl std::fmt::Display for Price {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{:02}", self.eur, self.cent)
     }
 }
 
@@ -401,6 +657,10 @@ impl<'de> Deserialize<'de> for Price {
 }
 
 impl Price {
+    pub fn eur(&self) -> u32 { self.eur }
+
+    pub fn cent(&self) -> u8 { self.cent }
+
     fn parse(s: &str) -> Option<Self> {
         let Some((eur, cent)) = s.split_once(",") else {
             tracing::warn!("could not parse price: '{s}'");
@@ -421,5 +681,18 @@ impl Price {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-
+    #[test]
+    fn prices_keep_leading_zero_cents() {
+        for s in ["3,05", "0,00", "2,50", "12,99"] {
+            let price: Price = serde_json::from_value(serde_json::json!(s)).unwrap();
+            assert_eq!(price.to_string(), s);
+            assert_eq!(serde_json::to_value(&price).unwrap(), serde_json::json!(s));
+        }
+        assert!(Price::parse("3,100").is_none());
+        assert!(Price::parse("3.05").is_none());
+    }
+}
@@ -0,0 +1,492 @@
+use std::fmt::Write;
+
+use chrono::{Datelike, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ClosureReason, DayState, MealAllergen, MealAttribute, MealDay, MealPlan, MensaMeal,
+};
+
+/// what [`MealDay::render`] produces
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Format {
+    #[default]
+    Json,
+    /// for terminals
+    #[serde(alias = "txt", alias = "plain")]
+    Text,
+    /// for chat posts
+    #[serde(alias = "md")]
+    Markdown,
+    /// a minimal standalone page
+    Html,
+}
+
+impl Format {
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Text => "text/plain; charset=utf-8",
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Html => "text/html; charset=utf-8",
+        }
+    }
+
+    /// `text/plain` and the like, without parameters
+    pub fn from_mime(mime: &str) -> Option<Self> {
+        Some(match mime {
+            "application/json" => Self::Json,
+            "text/plain" => Self::Text,
+            "text/markdown" => Self::Markdown,
+            "text/html" => Self::Html,
+            _ => None?,
+        })
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Text => "text",
+            Self::Markdown => "markdown",
+            Self::Html => "html",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum Lang {
+    De,
+    #[default]
+    En,
+}
+
+impl Lang {
+    /// one of [`MealPlans::LANGS`](crate::MealPlans::LANGS)
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "de" => Some(Self::De),
+            "en" => Some(Self::En),
+            _ => None,
+        }
+    }
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::De => "de",
+            Self::En => "en",
+        }
+    }
+
+    fn pick(self, de: &'static str, en: &'static str) -> &'static str {
+        match self {
+            Self::De => de,
+            Self::En => en,
+        }
+    }
+}
+
+/// whose price is shown
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "snake_case")]
+pub enum PriceGroup {
+    #[default]
+    Students,
+    Servants,
+    Guests,
+}
+
+impl PriceGroup {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Students => "students",
+            Self::Servants => "servants",
+            Self::Guests => "guests",
+        }
+    }
+}
+
+impl MealDay {
+    /// the day for people, prices are the students' ones
+    pub fn render(&self, format: Format, lang: Lang) -> String {
+        self.render_for(format, lang, PriceGroup::default())
+    }
+
+    pub fn render_for(&self, format: Format, lang: Lang, group: PriceGroup) -> String {
+        match format {
+            Format::Json => serde_json::to_string(self).expect("meal days should serialize"),
+            Format::Html => page(&date_title(self.date, lang), lang, &self.body(format, lang, group, 1)),
+            _ => self.body(format, lang, group, 1),
+        }
+    }
+
+    /// without the page around html, `level` is the heading level of the date
+    fn body(&self, format: Format, lang: Lang, group: PriceGroup, level: usize) -> String {
+        let mut out = String::new();
+        let title = date_title(self.date, lang);
+        heading(&mut out, format, level, &title);
+
+        if self.state != DayState::Open || self.is_empty() {
+            paragraph(&mut out, format, &state_text(&self.state, lang));
+            return out
+        }
+
+        let mut categories: Vec<_> = self.categories.iter()
+            .filter(|(_, v)| !v.is_empty())
+        .collect();
+        categories.sort_by_key(|(k, _)| *k);
+
+        let mut allergens = Vec::new();
+        for (category, meals) in categories {
+            heading(&mut out, format, level + 1, category);
+            if format == Format::Html { out.push_str("<ul>\n") }
+            for meal in meals {
+                meal_line(&mut out, format, lang, group, meal);
+                allergens.extend(meal.info().allergens().iter().copied());
+            }
+            match format {
+                Format::Html => out.push_str("</ul>\n"),
+                _ => out.push('\n'),
+            }
+        }
+
+        if !allergens.is_empty() {
+            allergens.sort_by_key(|v| v.sort_key());
+            allergens.dedup();
+            let legend = allergens.iter()
+                .map(|v| format!("{} {}", v.code(), v.name(lang)))
+                .collect::<Vec<_>>()
+            .join(", ");
+            paragraph(&mut out, format, &format!("{}: {legend}", lang.pick("Allergene", "Allergens")));
+        }
+        out
+    }
+}
+
+impl MealPlan {
+    /// every day of the plan, see [`MealDay::render`]
+    pub fn render(&self, format: Format, lang: Lang) -> String {
+        self.render_for(format, lang, PriceGroup::default())
+    }
+
+    pub fn render_for(&self, format: Format, lang: Lang, group: PriceGroup) -> String {
+        if format == Format::Json {
+            return serde_json::to_string(self).expect("meal plans should serialize")
+        }
+
+        let mut out = String::new();
+        heading(&mut out, format, 1, self.mensa());
+        for day in self.days() {
+            out.push_str(&day.body(format, lang, group, 2));
+        }
+        match format {
+            Format::Html => page(self.mensa(), lang, &out),
+            _ => out,
+        }
+    }
+}
+
+impl MealAllergen {
+    /// the marker the studierendenwerk prints on its menus (`21a`)
+    pub fn code(self) -> &'static str {
+        use MealAllergen::*;
+        match self {
+            Gluten => "21", Wheat => "21a", Rye => "21b", Barley => "21c",
+            Oats => "21d", Spelt => "21e", Hand => "21f",
+            Crustaceans => "22", Eggs => "23", Fish => "24", Peanuts => "25",
+            Nuts => "26", Almonds => "26a", Hazelnut => "26b", Wallnut => "26c",
+            Cashew => "26d", Pecan => "26e", Paranus => "26f", Pistacio => "26g",
+            Macadamia => "26h",
+            Cellery => "27", Soy => "28", Mustard => "29", MilkProducts => "30",
+            Sesame => "31", Sulfides => "32", Lupine => "33", Molluscs => "34",
+            NitriteSalt => "35", Yeast => "36",
+        }
+    }
+
+    pub fn name(self, lang: Lang) -> &'static str {
+        use MealAllergen::*;
+        match self {
+            Gluten => lang.pick("Gluten", "gluten"),
+            Wheat => lang.pick("Weizen", "wheat"),
+            Rye => lang.pick("Roggen", "rye"),
+            Barley => lang.pick("Gerste", "barley"),
+            Oats => lang.pick("Hafer", "oats"),
+            Spelt => lang.pick("Dinkel", "spelt"),
+            Hand => lang.pick("Kamut", "kamut"),
+            Crustaceans => lang.pick("Krebstiere", "crustaceans"),
+            Eggs => lang.pick("Eier", "eggs"),
+            Fish => lang.pick("Fisch", "fish"),
+            Peanuts => lang.pick("Erdnüsse", "peanuts"),
+            Nuts => lang.pick("Schalenfrüchte", "nuts"),
+            Almonds => lang.pick("Mandeln", "almonds"),
+            Hazelnut => lang.pick("Haselnüsse", "hazelnuts"),
+            Wallnut => lang.pick("Walnüsse", "walnuts"),
+            Cashew => lang.pick("Cashewnüsse", "cashews"),
+            Pecan => lang.pick("Pekannüsse", "pecans"),
+            Paranus => lang.pick("Paranüsse", "brazil nuts"),
+            Pistacio => lang.pick("Pistazien", "pistachios"),
+            Macadamia => lang.pick("Macadamianüsse", "macadamias"),
+            Cellery => lang.pick("Sellerie", "celery"),
+            Soy => lang.pick("Soja", "soy"),
+            Mustard => lang.pick("Senf", "mustard"),
+            MilkProducts => lang.pick("Milch", "milk"),
+            Sesame => lang.pick("Sesam", "sesame"),
+            Sulfides => lang.pick("Sulfite", "sulphites"),
+            Lupine => lang.pick("Lupinen", "lupin"),
+            Molluscs => lang.pick("Weichtiere", "molluscs"),
+            NitriteSalt => lang.pick("Nitritpökelsalz", "nitrite curing salt"),
+            Yeast => lang.pick("Hefe", "yeast"),
+        }
+    }
+
+    /// by number, then letter
+    fn sort_key(self) -> (u32, &'static str) {
+        let code = self.code();
+        (code[..2].parse().unwrap_or(u32::MAX), &code[2..])
+    }
+}
+
+impl MealAttribute {
+    pub fn name(self, lang: Lang) -> &'static str {
+        use MealAttribute::*;
+        match self {
+            Vegan => "vegan",
+            Fairtrade => "fairtrade",
+            ClimateFood => lang.pick("Klimaessen", "climate food"),
+            Vegetarian => lang.pick("vegetarisch", "vegetarian"),
+            SustainableFarming => lang.pick("nachhaltige Landwirtschaft", "sustainable farming"),
+            SustainableFishing => lang.pick("nachhaltige Fischerei", "sustainable fishing"),
+            Frozen => lang.pick("tiefgekühlt", "frozen"),
+        }
+    }
+}
+
+fn meal_line(out: &mut String, format: Format, lang: Lang, group: PriceGroup, meal: &MensaMeal) {
+    let price = meal.price().map(|v| {
+        let price = match group {
+            PriceGroup::Students => v.students(),
+            PriceGroup::Servants => v.servants(),
+            PriceGroup::Guests => v.guests(),
+        };
+        format!("{price} €")
+    });
+
+    let mut attributes: Vec<_> = meal.info().attributes().iter().map(|v| v.name(lang)).collect();
+    attributes.sort();
+    let mut allergens: Vec<_> = meal.info().allergens().iter().copied().collect();
+    allergens.sort_by_key(|v| v.sort_key());
+    let markers = allergens.iter().map(|v| v.code()).collect::<Vec<_>>().join(", ");
+
+    let title = meal.title();
+    let mut extra = Vec::new();
+    if let Some(price) = &price { extra.push(price.clone()) }
+    if !attributes.is_empty() { extra.push(attributes.join(", ")) }
+    if !markers.is_empty() { extra.push(format!("({markers})")) }
+
+    match format {
+        Format::Text => {
+            let _ = writeln!(out, "  {title}");
+            if let Some(v) = meal.description() { let _ = writeln!(out, "    {v}"); }
+            if !extra.is_empty() { let _ = writeln!(out, "    {}", extra.join("  ")); }
+        },
+        Format::Markdown => {
+            let _ = write!(out, "- **{}**", markdown_escape(title));
+            if let Some(v) = meal.description() { let _ = write!(out, " {}", markdown_escape(v)); }
+            if !extra.is_empty() { let _ = write!(out, " — {}", markdown_escape(&extra.join(" · "))); }
+            out.push('\n');
+        },
+        Format::Html => {
+            let _ = write!(out, "<li><strong>{}</strong>", html_escape(title));
+            if let Some(v) = meal.description() { let _ = write!(out, " {}", html_escape(v)); }
+            if !extra.is_empty() { let _ = write!(out, " <small>{}</small>", html_escape(&extra.join(" · "))); }
+            out.push_str("</li>\n");
+        },
+        Format::Json => unreachable!("json is not rendered line by line"),
+    }
+}
+
+fn heading(out: &mut String, format: Format, level: usize, text: &str) {
+    match format {
+        Format::Text => {
+            let _ = writeln!(out, "{text}");
+            let underline = if level == 1 { "=" } else { "-" };
+            let _ = writeln!(out, "{}", underline.repeat(text.chars().count()));
+        },
+        Format::Markdown => { let _ = writeln!(out, "{} {}\n", "#".repeat(level), markdown_escape(text)); },
+        Format::Html => { let _ = writeln!(out, "<h{level}>{}</h{level}>", html_escape(text)); },
+        Format::Json => {},
+    }
+}
+
+fn paragraph(out: &mut String, format: Format, text: &str) {
+    match format {
+        Format::Text => { let _ = writeln!(out, "{text}\n"); },
+        Format::Markdown => { let _ = writeln!(out, "_{}_\n", markdown_escape(text)); },
+        Format::Html => { let _ = writeln!(out, "<p>{}</p>", html_escape(text)); },
+        Format::Json => {},
+    }
+}
+
+fn page(title: &str, lang: Lang, body: &str) -> String {
+    format!(
+        "<!doctype html>\n<html lang=\"{}\">\n<head>\n<meta charset=\"utf-8\">\n\
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+        <title>{}</title>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        lang.code(), html_escape(title),
+    )
+}
+
+/// `Wednesday, 22.05.2024`
+fn date_title(date: NaiveDate, lang: Lang) -> String {
+    const DE: [&str; 7] = ["Montag", "Dienstag", "Mittwoch", "Donnerstag", "Freitag", "Samstag", "Sonntag"];
+    const EN: [&str; 7] = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+    let i = date.weekday().num_days_from_monday() as usize;
+    let weekday = match lang { Lang::De => DE[i], Lang::En => EN[i] };
+    format!("{weekday}, {}", date.format("%d.%m.%Y"))
+}
+
+fn state_text(state: &DayState, lang: Lang) -> String {
+    match state {
+        DayState::Open => lang.pick("Keine Gerichte.", "No meals.").into(),
+        DayState::Closed { reason } => {
+            let reason = match reason {
+                ClosureReason::Holiday(name) => name.clone(),
+                ClosureReason::Closure(Some(v)) => v.clone(),
+                ClosureReason::Closure(None) => lang.pick("geschlossen", "closed").into(),
+                ClosureReason::RegularHours => lang.pick("kein Öffnungstag", "not an opening day").into(),
+                ClosureReason::NoMeals => lang.pick("keine Gerichte", "no meals").into(),
+            };
+            format!("{} ({reason}).", lang.pick("Geschlossen", "Closed"))
+        },
+        DayState::NotYetPublished => lang.pick("Noch nicht veröffentlicht.", "Not published yet.").into(),
+        DayState::Unknown => lang.pick("Unbekannt.", "Unknown.").into(),
+    }
+}
+
+fn html_escape(v: &str) -> String {
+    v.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+    .replace('"', "&quot;")
+}
+
+fn markdown_escape(v: &str) -> String {
+    let mut out = String::with_capacity(v.len());
+    for c in v.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '<' | '>' | '#') { out.push('\\') }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn meal(title: &str, allergens: &[&str]) -> serde_json::Value {
+        json!({
+            "title": title,
+            "description": "with <b>sauce</b> & salad",
+            "price": { "students": "3,05", "servants": "4,10", "guests": "5,00" },
+            "info": {
+                "env_rating": { "health": null, "co2": null, "h2o": null },
+                "addatives": [],
+                "allergens": allergens,
+                "attributes": ["vegetarian"],
+            },
+            "id": title,
+        })
+    }
+
+    /// a wednesday
+    fn day() -> MealDay {
+        serde_json::from_value(json!({
+            "date": "2024-05-22",
+            "categories": {
+                "Main & Side": [
+                    meal("Fish & Chips <\"crispy\">", &["nuts", "wheat"]),
+                    meal("Pasta", &["wheat", "gluten"]),
+                ],
+                "Empty": [],
+            },
+        })).unwrap()
+    }
+
+    #[test]
+    fn html_escapes_meal_names() {
+        let html = day().render(Format::Html, Lang::En);
+
+        assert!(html.contains("<title>Wednesday, 22.05.2024</title>"), "{html}");
+        assert!(html.contains("<h2>Main &amp; Side</h2>"), "{html}");
+        assert!(html.contains("<strong>Fish &amp; Chips &lt;&quot;crispy&quot;&gt;</strong>"), "{html}");
+        assert!(html.contains("with &lt;b&gt;sauce&lt;/b&gt; &amp; salad"), "{html}");
+        assert!(!html.contains("<b>") && !html.contains("\"crispy\""), "{html}");
+        // categories without meals are left out
+        assert!(!html.contains("Empty"), "{html}");
+    }
+
+    #[test]
+    fn markdown_escapes_meal_names() {
+        let mut day = day();
+        day.categories.insert("Main & Side".into(), vec![
+            serde_json::from_value(meal("*Bold* [link]", &[])).unwrap(),
+        ]);
+        let md = day.render(Format::Markdown, Lang::En);
+
+        assert!(md.contains("- **\\*Bold\\* \\[link\\]**"), "{md}");
+    }
+
+    #[test]
+    fn allergen_legend_is_sorted_and_deduplicated() {
+        let text = day().render(Format::Text, Lang::En);
+        assert!(text.contains("Allergens: 21 gluten, 21a wheat, 26 nuts\n"), "{text}");
+        // the markers of each meal, sorted the same way
+        assert!(text.contains("(21a, 26)") && text.contains("(21, 21a)"), "{text}");
+
+        let text = day().render(Format::Text, Lang::De);
+        assert!(text.contains("Allergene: 21 Gluten, 21a Weizen, 26 Schalenfrüchte\n"), "{text}");
+    }
+
+    #[test]
+    fn no_allergen_legend_without_allergens() {
+        let mut day = day();
+        day.categories.insert("Main & Side".into(), vec![
+            serde_json::from_value(meal("Pasta", &[])).unwrap(),
+        ]);
+
+        assert!(!day.render(Format::Text, Lang::En).contains("Allergens"));
+    }
+
+    #[test]
+    fn prices_are_those_of_the_group() {
+        let text = day().render(Format::Text, Lang::En);
+        assert!(text.contains("3,05 €  vegetarian"), "{text}");
+
+        for (group, price, not) in [
+            (PriceGroup::Students, "3,05 €", ["4,10 €", "5,00 €"]),
+            (PriceGroup::Servants, "4,10 €", ["3,05 €", "5,00 €"]),
+            (PriceGroup::Guests, "5,00 €", ["3,05 €", "4,10 €"]),
+        ] {
+            let text = day().render_for(Format::Text, Lang::En, group);
+            assert_eq!(text.matches(price).count(), 2, "{group:?}: {text}");
+            assert!(not.iter().all(|v| !text.contains(v)), "{group:?}: {text}");
+        }
+    }
+
+    #[test]
+    fn closed_days_say_why() {
+        let mut day = day();
+        day.state = DayState::Closed { reason: ClosureReason::Holiday("Pfingstmontag".into()) };
+
+        assert_eq!(
+            day.render(Format::Text, Lang::En),
+            "Wednesday, 22.05.2024\n=====================\nClosed (Pfingstmontag).\n\n",
+        );
+    }
+}
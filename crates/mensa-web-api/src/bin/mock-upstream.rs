@@ -0,0 +1,47 @@
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use clap::Parser;
+use mensa_web_api::mock_upstream::{MockBehaviour, MockUpstream};
+
+/// serves recorded getdata.php fixtures, point `upstream.url` at it
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// directory with `<mensa_id>.<lang>.json` files (defaults to the bundled fixtures)
+    #[arg(long)]
+    fixtures: Option<PathBuf>,
+    #[arg(long, default_value = "127.0.0.1:3001")]
+    address: SocketAddr,
+    /// delay every response
+    #[arg(long, default_value_t = 0)]
+    latency_ms: u64,
+    /// chance (0..=1) to answer with a 500
+    #[arg(long, default_value_t = 0.0)]
+    error_rate: f64,
+    /// chance (0..=1) to answer with broken json
+    #[arg(long, default_value_t = 0.0)]
+    malformed_rate: f64,
+    /// move the recorded days into the current week
+    #[arg(long)]
+    shift_dates: bool,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt().with_writer(std::io::stderr).init();
+    let args = Args::parse();
+
+    let mock = args.fixtures.map_or_else(MockUpstream::bundled, MockUpstream::new)
+        .with_behaviour(MockBehaviour {
+            latency: Duration::from_millis(args.latency_ms),
+            error_rate: args.error_rate,
+            malformed_rate: args.malformed_rate,
+            shift_dates: args.shift_dates,
+        });
+
+    let addr = mock.serve(args.address).await?;
+    tracing::info!("mock upstream on {}", MockUpstream::url(addr));
+
+    _ = tokio::signal::ctrl_c().await;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+
+/// source of the current time, localized to the configured timezone
+///
+/// everything that asks for "now" or "today" should go through this,
+/// so tests can pin the time with [`Clock::fixed`]
+#[derive(Clone)]
+pub struct Clock {
+    tz: Tz,
+    now: Arc<dyn Fn() -> DateTime<Utc> + Send + Sync>,
+}
+
+impl Clock {
+    pub fn system(tz: Tz) -> Self {
+        Self { tz, now: Arc::new(Utc::now) }
+    }
+
+    pub fn fixed(tz: Tz, at: DateTime<Utc>) -> Self {
+        Self::from_fn(tz, move || at)
+    }
+
+    pub fn from_fn(
+        tz: Tz,
+        now: impl Fn() -> DateTime<Utc> + Send + Sync + 'static,
+    ) -> Self {
+        Self { tz, now: Arc::new(now) }
+    }
+
+    pub fn tz(&self) -> Tz { self.tz }
+
+    pub fn now(&self) -> DateTime<Tz> {
+        (self.now)().with_timezone(&self.tz)
+    }
+
+    /// the current date in the configured timezone (not utc)
+    pub fn today(&self) -> NaiveDate {
+        self.now().date_naive()
+    }
+}
+
+impl std::fmt::Debug for Clock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Clock")
+            .field("tz", &self.tz)
+            .field("now", &self.now())
+        .finish()
+    }
+}
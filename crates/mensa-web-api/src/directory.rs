@@ -0,0 +1,152 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::opening_hours::OpeningHours;
+
+const BUNDLED: &str = include_str!("../data/mensas.toml");
+
+/// all known studierendenwerk locations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MensaDirectory {
+    /// bumped whenever the list changes
+    pub version: u32,
+    #[serde(rename = "mensa")]
+    mensas: Vec<MensaInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct MensaInfo {
+    /// the id used by the upstream api
+    pub id: String,
+    pub name: String,
+    pub campus: String,
+    pub address: String,
+    pub coordinates: Coordinates,
+    /// the regular hours like `Mo-Fr 11:00-15:00`, always taken from the opening hours
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opening_hours: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Coordinates {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+impl Coordinates {
+    /// great circle distance in meters (haversine)
+    pub fn distance_to(&self, other: &Coordinates) -> f64 {
+        const EARTH_RADIUS: f64 = 6_371_000.0;
+
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.lon - self.lon).to_radians();
+
+        let a = (d_lat / 2.0).sin().powi(2)
+            + lat1.cos() * lat2.cos() * (d_lon / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().asin()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (-90.0..=90.0).contains(&self.lat) && (-180.0..=180.0).contains(&self.lon)
+    }
+}
+
+impl MensaDirectory {
+    /// the directory shipped with the binary (`data/mensas.toml`)
+    pub fn bundled() -> Self {
+        toml::from_str(BUNDLED).expect("bundled mensa directory should be valid")
+    }
+
+    pub async fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = tokio::fs::read_to_string(path).await?;
+        Ok(toml::from_str(&data)?)
+    }
+
+    /// fills in [`MensaInfo::opening_hours`], so they are only maintained once
+    pub fn with_hours(mut self, hours: &OpeningHours) -> Self {
+        for mensa in &mut self.mensas {
+            mensa.opening_hours = hours.summary(&mensa.id);
+        }
+        self
+    }
+
+    pub fn get(&self, id: &str) -> Option<&MensaInfo> {
+        self.mensas.iter().find(|v| v.id == id)
+    }
+
+    pub fn contains(&self, id: &str) -> bool { self.get(id).is_some() }
+
+    /// ids upstream could know, short and alphanumeric
+    pub fn is_valid_id(id: &str) -> bool {
+        (1..=16).contains(&id.len()) && id.chars().all(|c| c.is_ascii_alphanumeric())
+    }
+
+    pub fn mensas(&self) -> std::slice::Iter<'_, MensaInfo> {
+        self.mensas.iter()
+    }
+
+    /// case insensitive search in name and campus
+    pub fn search<'a>(
+        &'a self, query: Option<&str>, campus: Option<&str>,
+    ) -> impl Iterator<Item = &'a MensaInfo> {
+        let query = query.map(str::to_lowercase);
+        let campus = campus.map(str::to_lowercase);
+
+        self.mensas.iter().filter(move |v| {
+            query.as_ref().is_none_or(|q| {
+                v.name.to_lowercase().contains(q)
+                    || v.campus.to_lowercase().contains(q)
+            }) && campus.as_ref().is_none_or(|c| {
+                v.campus.to_lowercase().contains(c)
+            })
+        })
+    }
+
+    /// mensas within `radius` meters of `pos`, closest first
+    pub fn nearby(
+        &self, pos: &Coordinates, radius: Option<f64>,
+    ) -> Vec<(f64, &MensaInfo)> {
+        let mut mensas: Vec<_> = self.mensas.iter()
+            .map(|v| (v.coordinates.distance_to(pos), v))
+            .filter(|(d, _)| radius.is_none_or(|r| *d <= r))
+        .collect();
+        mensas.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        mensas
+    }
+
+    /// mensas that might have been meant by an unknown `id`
+    pub fn suggest(&self, id: &str) -> Vec<&MensaInfo> {
+        const MAX_SUGGESTIONS: usize = 5;
+
+        let by_name: Vec<_> = self.search(Some(id), None)
+            .take(MAX_SUGGESTIONS)
+        .collect();
+        if !by_name.is_empty() { return by_name }
+
+        let mut by_id: Vec<_> = self.mensas.iter()
+            .map(|v| (edit_distance(&v.id, id), v))
+            .filter(|(d, _)| *d <= 1)
+        .collect();
+        by_id.sort_by_key(|(d, _)| *d);
+        by_id.into_iter().take(MAX_SUGGESTIONS).map(|(_, v)| v).collect()
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<_> = b.chars().collect();
+    let mut prev: Vec<_> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            curr[j + 1] = (prev[j] + (ca != *cb) as usize)
+                .min(prev[j + 1] + 1)
+            .min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+
+    prev[b.len()]
+}
@@ -0,0 +1,144 @@
+use chrono::{Datelike, Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+/// public holidays in berlin, plus configured extra days
+#[derive(Debug, Clone, Default)]
+pub struct HolidayCalendar {
+    builtin: bool,
+    extra: Vec<Holiday>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Holiday {
+    pub date: NaiveDate,
+    pub name: String,
+}
+
+impl HolidayCalendar {
+    pub fn new(builtin: bool, extra: Vec<Holiday>) -> Self {
+        Self { builtin, extra }
+    }
+
+    /// the name of the holiday on `day`, configured days win over builtin ones
+    pub fn get(&self, day: NaiveDate) -> Option<String> {
+        if let Some(v) = self.extra.iter().find(|v| v.date == day) {
+            return Some(v.name.clone())
+        }
+        if !self.builtin { return None }
+
+        berlin(day.year()).into_iter()
+            .find(|(d, _)| *d == day)
+        .map(|(_, name)| name.into())
+    }
+}
+
+/// statutory holidays in berlin for `year`
+fn berlin(year: i32) -> Vec<(NaiveDate, &'static str)> {
+    let date = |m, d| NaiveDate::from_ymd_opt(year, m, d);
+    let Some(easter) = easter_sunday(year) else { return Vec::new() };
+    let from_easter = |offset: i64| if offset < 0 {
+        easter.checked_sub_days(Days::new(offset.unsigned_abs()))
+    } else {
+        easter.checked_add_days(Days::new(offset as u64))
+    };
+
+    [
+        (date(1, 1), "Neujahr"),
+        // since 2019
+        (date(3, 8).filter(|_| year >= 2019), "Internationaler Frauentag"),
+        (from_easter(-2), "Karfreitag"),
+        (from_easter(1), "Ostermontag"),
+        (date(5, 1), "Tag der Arbeit"),
+        // only on its 75th and 80th anniversary
+        (date(5, 8).filter(|_| matches!(year, 2020 | 2025)), "Tag der Befreiung"),
+        (from_easter(39), "Christi Himmelfahrt"),
+        (from_easter(50), "Pfingstmontag"),
+        (date(10, 3), "Tag der Deutschen Einheit"),
+        (date(12, 25), "1. Weihnachtstag"),
+        (date(12, 26), "2. Weihnachtstag"),
+    ].into_iter().filter_map(|(d, name)| Some((d?, name))).collect()
+}
+
+/// anonymous gregorian algorithm (meeus/jones/butcher)
+fn easter_sunday(year: i32) -> Option<NaiveDate> {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate { s.parse().unwrap() }
+
+    #[test]
+    fn easter_sundays() {
+        for (year, easter) in [
+            (1818, "1818-03-22"),
+            (1943, "1943-04-25"),
+            (2000, "2000-04-23"),
+            (2019, "2019-04-21"),
+            (2024, "2024-03-31"),
+            (2025, "2025-04-20"),
+            (2026, "2026-04-05"),
+            (2038, "2038-04-25"),
+        ] {
+            assert_eq!(easter_sunday(year), Some(date(easter)), "{year}");
+        }
+    }
+
+    #[test]
+    fn berlin_holidays() {
+        for (year, expected) in [
+            (2018, &[
+                "2018-01-01", "2018-03-30", "2018-04-02", "2018-05-01",
+                "2018-05-10", "2018-05-21", "2018-10-03", "2018-12-25", "2018-12-26",
+            ][..]),
+            (2020, &[
+                "2020-01-01", "2020-03-08", "2020-04-10", "2020-04-13", "2020-05-01", "2020-05-08",
+                "2020-05-21", "2020-06-01", "2020-10-03", "2020-12-25", "2020-12-26",
+            ]),
+            (2024, &[
+                "2024-01-01", "2024-03-08", "2024-03-29", "2024-04-01", "2024-05-01",
+                "2024-05-09", "2024-05-20", "2024-10-03", "2024-12-25", "2024-12-26",
+            ]),
+            (2025, &[
+                "2025-01-01", "2025-03-08", "2025-04-18", "2025-04-21", "2025-05-01", "2025-05-08",
+                "2025-05-29", "2025-06-09", "2025-10-03", "2025-12-25", "2025-12-26",
+            ]),
+        ] {
+            let days: Vec<_> = berlin(year).into_iter().map(|(d, _)| d).collect();
+            let expected: Vec<_> = expected.iter().copied().map(date).collect();
+            assert_eq!(days, expected, "{year}");
+        }
+    }
+
+    #[test]
+    fn configured_days_win() {
+        let calendar = HolidayCalendar::new(true, vec![Holiday {
+            date: date("2024-05-20"),
+            name: "Betriebsausflug".into(),
+        }]);
+        assert_eq!(calendar.get(date("2024-05-20")).as_deref(), Some("Betriebsausflug"));
+        assert_eq!(calendar.get(date("2024-05-09")).as_deref(), Some("Christi Himmelfahrt"));
+        assert_eq!(calendar.get(date("2024-05-10")), None);
+
+        let calendar = HolidayCalendar::new(false, Vec::new());
+        assert_eq!(calendar.get(date("2024-05-09")), None);
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::BTreeMap,
+    panic::AssertUnwindSafe,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use futures::{future::BoxFuture, FutureExt};
+use serde::Serialize;
+
+use crate::{clock::Clock, config::JobKind};
+
+/// what a job does, `Ok` carries a short summary of the run
+pub type JobTask = Arc<dyn Fn() -> BoxFuture<'static, anyhow::Result<String>> + Send + Sync>;
+
+/// every configured job and how its runs went
+#[derive(Clone)]
+pub struct JobRegistry {
+    jobs: Arc<Mutex<BTreeMap<String, Entry>>>,
+    clock: Clock,
+}
+
+struct Entry {
+    status: JobStatus,
+    task: JobTask,
+    /// parsed once, `None` for intervals
+    cron: Option<cron::Schedule>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub kind: JobKind,
+    /// cron or interval
+    pub schedule: String,
+    pub timezone: Tz,
+    /// `false` if jobs are disabled or the scheduler rejected it
+    pub scheduled: bool,
+    pub running: bool,
+    pub runs: u64,
+    /// runs that were skipped because the job was still running
+    pub skipped: u64,
+    pub failures: u64,
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_duration_ms: Option<u64>,
+    pub last_outcome: Option<JobOutcome>,
+    pub last_result: Option<String>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobOutcome {
+    Success,
+    Failure,
+}
+
+/// what [`JobRegistry::run`] did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobRun {
+    Done,
+    /// skipped, the job was still running
+    AlreadyRunning,
+    NotFound,
+}
+
+impl JobRegistry {
+    pub fn new(clock: Clock) -> Self {
+        Self { jobs: Default::default(), clock }
+    }
+
+    pub fn register(
+        &self, name: &str, kind: JobKind,
+        schedule: &str, timezone: Tz,
+        task: JobTask,
+    ) {
+        self.jobs.lock().unwrap().insert(name.into(), Entry {
+            status: JobStatus {
+                name: name.into(),
+                kind,
+                schedule: schedule.into(),
+                timezone,
+                scheduled: false,
+                running: false,
+                runs: 0,
+                skipped: 0,
+                failures: 0,
+                last_run: None,
+                last_duration_ms: None,
+                last_outcome: None,
+                last_result: None,
+                last_error: None,
+            },
+            task,
+            cron: cron::Schedule::from_str(schedule).ok(),
+        });
+    }
+
+    /// records whether the scheduler accepted the job
+    pub fn scheduled(&self, name: &str, res: Result<(), String>) {
+        let mut jobs = self.jobs.lock().unwrap();
+        let Some(entry) = jobs.get_mut(name) else { return };
+        entry.status.scheduled = res.is_ok();
+        if let Err(err) = res {
+            tracing::error!("could not schedule job {name}: {err}");
+            entry.status.last_error = Some(format!("could not schedule: {err}"));
+        }
+    }
+
+    /// runs the job now and records the outcome, unless it is already
+    /// running (scheduled and manual runs do not overlap)
+    pub async fn run(&self, name: &str) -> JobRun {
+        let task = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let Some(entry) = jobs.get_mut(name) else { return JobRun::NotFound };
+            if entry.status.running {
+                tracing::warn!("job {name} is still running, skipping this run");
+                entry.status.skipped += 1;
+                return JobRun::AlreadyRunning
+            }
+            entry.status.running = true;
+            entry.task.clone()
+        };
+
+        tracing::info!("running job {name}");
+        let (started, start) = (self.clock.now().with_timezone(&Utc), Instant::now());
+        // a panic would leave the job running forever
+        let res = AssertUnwindSafe(task()).catch_unwind().await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("job panicked")));
+        let took = start.elapsed();
+
+        match &res {
+            Ok(v) => tracing::info!("job {name} done (took {took:?}): {v}"),
+            Err(err) => tracing::error!("job {name} failed (took {took:?}): {err:#}"),
+        }
+
+        let mut jobs = self.jobs.lock().unwrap();
+        if let Some(entry) = jobs.get_mut(name) {
+            let status = &mut entry.status;
+            status.running = false;
+            status.runs += 1;
+            status.last_run = Some(started);
+            status.last_duration_ms = Some(took.as_millis() as u64);
+            match res {
+                Ok(v) => {
+                    status.last_outcome = Some(JobOutcome::Success);
+                    status.last_result = Some(v);
+                    status.last_error = None;
+                },
+                Err(err) => {
+                    status.failures += 1;
+                    status.last_outcome = Some(JobOutcome::Failure);
+                    status.last_result = None;
+                    status.last_error = Some(format!("{err:#}"));
+                },
+            }
+        }
+        JobRun::Done
+    }
+
+    /// the next time a cron job of `kind` runs after `after`
+    pub fn next_run(&self, kind: JobKind, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.jobs.lock().unwrap().values()
+            .filter(|v| v.status.kind == kind)
+            .filter_map(|v| {
+                v.cron.as_ref()?.after(&after.with_timezone(&v.status.timezone)).next()
+            })
+            .map(|v| v.with_timezone(&Utc))
+        .min()
+    }
+
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.jobs.lock().unwrap().values()
+            .map(|v| v.status.clone())
+        .collect()
+    }
+}
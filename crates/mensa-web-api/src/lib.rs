@@ -0,0 +1,11 @@
+
+pub mod routes;
+pub mod config;
+pub mod clock;
+pub mod directory;
+pub mod opening_hours;
+pub mod holidays;
+#[cfg(feature = "mock")]
+pub mod mock_upstream;
+pub mod store;
+pub mod jobs;
@@ -1,24 +1,66 @@
+use std::path::PathBuf;
 
-mod routes;
-mod config;
+use clap::Parser;
+use mensa_web_api::{config, routes};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "serves the meal plans of the studierendenwerk berlin")]
+struct Args {
+    /// path to the config file (defaults to ./mensa_api.toml if it exists)
+    #[arg(long, env = config::ENV_CONFIG)]
+    config: Option<PathBuf>,
+    /// validate the config, print the effective config and exit
+    #[arg(long)]
+    check_config: bool,
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt().init();
+    tracing_subscriber::fmt().with_writer(std::io::stderr).init();
+
+    let args = Args::parse();
+    let config = config::read(args.config.as_deref()).await?;
 
-    let config = config::read().await?;
+    if args.check_config {
+        println!("{}", toml::to_string_pretty(&config.redacted())?);
+        return Ok(())
+    }
 
-    let app = routes::make_router(&config).await;
+    let deps = routes::Deps::from_config(&config).await;
+    let app = routes::make_app(&config, deps).await;
 
-    let addr = ("0.0.0.0", 3000);
+    let addr = (config.server.address, config.server.port);
     let listener = tokio::net::TcpListener::bind(addr).await?;
     let addr = listener.local_addr()?;
     tracing::info!("starting server on http://{addr}");
 
-    axum::serve::serve(listener, app).with_graceful_shutdown(async {
-        _ = tokio::signal::ctrl_c().await;
-    }).await?;
+    axum::serve::serve(listener, app.router.clone())
+        .with_graceful_shutdown(shutdown_signal())
+    .await?;
 
+    tracing::info!("shutting down");
+    app.shutdown().await;
     Ok(())
 }
 
+/// ctrl-c, or the SIGTERM that deploys and container stops send
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut v) => { v.recv().await; },
+            Err(err) => {
+                tracing::error!("could not listen for SIGTERM: {err}");
+                std::future::pending().await
+            },
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate => tracing::info!("got SIGTERM"),
+    }
+}
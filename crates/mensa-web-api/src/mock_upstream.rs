@@ -0,0 +1,230 @@
+use std::{
+    net::SocketAddr,
+    path::PathBuf,
+    sync::{atomic::{AtomicUsize, Ordering}, Arc, RwLock},
+    time::Duration,
+};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{Datelike, Days, NaiveDate, Utc};
+use serde_json::Value;
+use tokio::net::{TcpListener, ToSocketAddrs};
+
+/// serves recorded `getdata.php` responses, for tests and offline development
+///
+/// fixtures are looked up as `<mensa_id>.<lang>.json` and `<mensa_id>.json`
+/// (in every fixture directory, in order), unknown mensas get an empty result
+/// (like upstream does)
+#[derive(Clone)]
+pub struct MockUpstream {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    fixtures: Vec<PathBuf>,
+    behaviour: RwLock<MockBehaviour>,
+    requests: AtomicUsize,
+    /// requests left that fail no matter the behaviour
+    fail_next: AtomicUsize,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+/// counts a request as in flight until dropped
+struct InFlight<'a>(&'a Inner);
+
+impl<'a> InFlight<'a> {
+    fn start(inner: &'a Inner) -> Self {
+        let now = inner.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        inner.max_in_flight.fetch_max(now, Ordering::SeqCst);
+        Self(inner)
+    }
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MockBehaviour {
+    /// added to every response
+    pub latency: Duration,
+    /// chance (0..=1) to answer with a 500
+    pub error_rate: f64,
+    /// chance (0..=1) to answer with broken json
+    pub malformed_rate: f64,
+    /// move the recorded days into the current week
+    pub shift_dates: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct GetData {
+    mensa_id: String,
+    lang: Option<String>,
+}
+
+impl MockUpstream {
+    pub fn new(fixtures: impl Into<PathBuf>) -> Self {
+        Self::layered([fixtures.into()])
+    }
+
+    /// looks in `dirs` in order, e.g. recorded fixtures and then hand written ones
+    pub fn layered(dirs: impl IntoIterator<Item = PathBuf>) -> Self {
+        Self { inner: Arc::new(Inner {
+            fixtures: dirs.into_iter().collect(),
+            behaviour: RwLock::new(MockBehaviour::default()),
+            requests: AtomicUsize::new(0),
+            fail_next: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            max_in_flight: AtomicUsize::new(0),
+        }) }
+    }
+
+    /// the fixtures recorded in this repo (`fixtures/getdata`)
+    pub fn bundled() -> Self {
+        Self::new(concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/getdata"))
+    }
+
+    pub fn with_behaviour(self, behaviour: MockBehaviour) -> Self {
+        self.set_behaviour(behaviour);
+        self
+    }
+
+    /// can be changed while serving
+    pub fn set_behaviour(&self, behaviour: MockBehaviour) {
+        *self.inner.behaviour.write().unwrap() = behaviour;
+    }
+
+    pub fn behaviour(&self) -> MockBehaviour {
+        self.inner.behaviour.read().unwrap().clone()
+    }
+
+    /// the next `n` requests answer with a 500
+    pub fn fail_next(&self, n: usize) {
+        self.inner.fail_next.store(n, Ordering::SeqCst);
+    }
+
+    /// number of requests served so far
+    pub fn requests(&self) -> usize {
+        self.inner.requests.load(Ordering::SeqCst)
+    }
+
+    /// the most requests that were answered at the same time
+    pub fn max_concurrent(&self) -> usize {
+        self.inner.max_in_flight.load(Ordering::SeqCst)
+    }
+
+    pub fn router(&self) -> Router {
+        Router::new()
+            .route("/api/getdata.php", get(get_data))
+        .with_state(self.clone())
+    }
+
+    /// serves in the background, returns the bound address
+    pub async fn serve(&self, addr: impl ToSocketAddrs) -> std::io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr).await?;
+        let addr = listener.local_addr()?;
+        let router = self.router();
+        tokio::spawn(async move {
+            if let Err(err) = axum::serve(listener, router).await {
+                tracing::error!("mock upstream stopped: {err}");
+            }
+        });
+
+        Ok(addr)
+    }
+
+    /// the url to use as `upstream.url`
+    pub fn url(addr: SocketAddr) -> String {
+        format!("http://{addr}/api/getdata.php")
+    }
+
+    async fn fixture(&self, mensa: &str, lang: Option<&str>) -> Option<String> {
+        let candidates = self.inner.fixtures.iter().flat_map(|dir| {
+            lang.map(|l| dir.join(format!("{mensa}.{l}.json")))
+                .into_iter()
+            .chain([dir.join(format!("{mensa}.json"))])
+        });
+
+        for path in candidates {
+            if let Ok(v) = tokio::fs::read_to_string(&path).await { return Some(v) }
+        }
+        None
+    }
+}
+
+async fn get_data(
+    Query(q): Query<GetData>,
+    State(mock): State<MockUpstream>,
+) -> Response {
+    mock.inner.requests.fetch_add(1, Ordering::SeqCst);
+    let _in_flight = InFlight::start(&mock.inner);
+    let behaviour = mock.behaviour();
+    tracing::info!("mock upstream: {q:?}");
+
+    if !behaviour.latency.is_zero() { tokio::time::sleep(behaviour.latency).await }
+
+    let forced = mock.inner.fail_next
+        .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+    .is_ok();
+    if forced || rand::random::<f64>() < behaviour.error_rate {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "mock error").into_response()
+    }
+
+    // mensa ids are passed through to a path
+    if !q.mensa_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return (StatusCode::BAD_REQUEST, "invalid mensa_id").into_response()
+    }
+
+    let lang = q.lang.as_deref().unwrap_or("de");
+    let body = match mock.fixture(&q.mensa_id, Some(lang)).await {
+        Some(v) if behaviour.shift_dates => shift_dates(&v).unwrap_or(v),
+        Some(v) => v,
+        None => r#"{"result":[],"mensaname":""}"#.into(),
+    };
+
+    if rand::random::<f64>() < behaviour.malformed_rate {
+        let half = body.char_indices().nth(body.chars().count() / 2).map_or(0, |(i, _)| i);
+        return ([("content-type", "application/json")], body[..half].to_string()).into_response()
+    }
+
+    ([("content-type", "application/json")], body).into_response()
+}
+
+/// moves all days so the first one lies in the current week
+fn shift_dates(body: &str) -> Option<String> {
+    let mut data: Value = serde_json::from_str(body).ok()?;
+    let days = data.get_mut("result")?.as_array_mut()?;
+
+    let parse = |v: &Value| v.pointer("/tag/datum_iso")?
+        .as_str()?
+    .parse::<NaiveDate>().ok();
+    let first = days.iter().filter_map(parse).min()?;
+
+    let monday = |d: NaiveDate| d.week(chrono::Weekday::Mon).first_day();
+    let offset = (monday(Utc::now().date_naive()) - monday(first)).num_days();
+
+    for day in days {
+        let Some(date) = parse(day) else { continue };
+        let shifted = if offset < 0 {
+            date.checked_sub_days(Days::new(offset.unsigned_abs()))
+        } else {
+            date.checked_add_days(Days::new(offset as u64))
+        }?;
+
+        let tag = day.get_mut("tag")?;
+        tag["datum_iso"] = shifted.to_string().into();
+        tag["timestamp"] = shifted.and_hms_opt(0, 0, 0)?.and_utc().timestamp().into();
+        tag["jahrestag"] = shifted.ordinal().to_string().into();
+    }
+
+    serde_json::to_string(&data).ok()
+}
@@ -0,0 +1,380 @@
+use std::{collections::HashMap, path::Path};
+
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone, Weekday};
+use serde::{Deserialize, Serialize};
+
+const BUNDLED: &str = include_str!("../data/opening_hours.toml");
+
+/// regular weekly hours per mensa, with date specific exceptions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpeningHours {
+    #[serde(default)]
+    pub version: u32,
+    /// used for mensas without their own entry
+    #[serde(default)]
+    default: MensaHours,
+    #[serde(default)]
+    mensa: HashMap<String, MensaHours>,
+    /// apply to every mensa (semester breaks, ...)
+    #[serde(default)]
+    exceptions: Vec<HoursException>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MensaHours {
+    #[serde(default)]
+    weekly: Option<HashMap<Weekday, Vec<TimeRange>>>,
+    #[serde(default)]
+    exceptions: Vec<HoursException>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HoursException {
+    /// only used for exceptions from the config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mensa: Option<String>,
+    pub from: NaiveDate,
+    /// inclusive, defaults to `from`
+    pub to: Option<NaiveDate>,
+    /// empty means closed
+    #[serde(default)]
+    pub hours: Vec<TimeRange>,
+    pub reason: Option<String>,
+}
+
+impl HoursException {
+    fn contains(&self, day: NaiveDate) -> bool {
+        self.from <= day && day <= self.to.unwrap_or(self.from)
+    }
+}
+
+/// `11:00-14:30`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+}
+
+impl std::str::FromStr for TimeRange {
+    type Err = chrono::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (open, close) = s.split_once('-').unwrap_or((s, ""));
+        Ok(Self {
+            open: NaiveTime::parse_from_str(open.trim(), "%H:%M")?,
+            close: NaiveTime::parse_from_str(close.trim(), "%H:%M")?,
+        })
+    }
+}
+
+impl Serialize for TimeRange {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where S: serde::Serializer
+    {
+        serializer.serialize_str(&format!(
+            "{}-{}", self.open.format("%H:%M"), self.close.format("%H:%M"),
+        ))
+    }
+}
+
+impl<'de> Deserialize<'de> for TimeRange {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where D: serde::Deserializer<'de>
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(|_| serde::de::Error::invalid_value(
+            serde::de::Unexpected::Str(&s),
+            &"a time range like 11:00-14:30",
+        ))
+    }
+}
+
+/// the hours of a single day
+#[derive(Debug, Clone)]
+pub struct DayHours<'a> {
+    pub hours: &'a [TimeRange],
+    /// set if an exception applies
+    pub reason: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenState { Open, ClosingSoon, Closed }
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct MensaStatus<T: TimeZone> {
+    pub status: OpenState,
+    /// why it is closed, like `holiday` or `closure`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// the name of the holiday or the text of the closure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub closes_at: Option<DateTime<T>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>, format = DateTime)]
+    pub opens_at: Option<DateTime<T>>,
+}
+
+impl OpeningHours {
+    /// the hours shipped with the binary (`data/opening_hours.toml`)
+    pub fn bundled() -> Self {
+        toml::from_str(BUNDLED).expect("bundled opening hours should be valid")
+    }
+
+    pub async fn load(path: &Path) -> anyhow::Result<Self> {
+        let data = tokio::fs::read_to_string(path).await?;
+        Ok(toml::from_str(&data)?)
+    }
+
+    /// adds exceptions, the ones added last take precedence
+    pub fn add_exceptions(&mut self, exceptions: impl IntoIterator<Item = HoursException>) {
+        for ex in exceptions {
+            match ex.mensa.clone() {
+                Some(id) => self.mensa.entry(id).or_default().exceptions.push(ex),
+                None => self.exceptions.push(ex),
+            }
+        }
+    }
+
+    pub fn day(&self, mensa: &str, day: NaiveDate) -> DayHours<'_> {
+        let own = self.mensa.get(mensa);
+
+        // mensa specific exceptions win over global ones
+        let exception = own.into_iter()
+            .flat_map(|v| v.exceptions.iter().rev())
+            .chain(self.exceptions.iter().rev())
+        .find(|v| v.contains(day));
+
+        if let Some(ex) = exception {
+            return DayHours { hours: &ex.hours, reason: ex.reason.as_deref() }
+        }
+
+        let weekly = own.and_then(|v| v.weekly.as_ref())
+            .or(self.default.weekly.as_ref());
+        DayHours {
+            hours: weekly
+                .and_then(|v| v.get(&day.weekday()))
+                .map(Vec::as_slice)
+            .unwrap_or_default(),
+            reason: None,
+        }
+    }
+
+    /// the regular weekly hours like `Mo-Fr 11:00-15:00`, without exceptions
+    pub fn summary(&self, mensa: &str) -> Option<String> {
+        const DAYS: [(Weekday, &str); 7] = [
+            (Weekday::Mon, "Mo"), (Weekday::Tue, "Tu"), (Weekday::Wed, "We"),
+            (Weekday::Thu, "Th"), (Weekday::Fri, "Fr"), (Weekday::Sat, "Sa"), (Weekday::Sun, "Su"),
+        ];
+
+        let weekly = self.mensa.get(mensa)
+            .and_then(|v| v.weekly.as_ref())
+            .or(self.default.weekly.as_ref())?;
+        let hours = |day| weekly.get(&day).map(Vec::as_slice).unwrap_or_default();
+
+        // consecutive days with the same hours are grouped
+        let mut groups: Vec<(&str, &str, &[TimeRange])> = Vec::new();
+        for (day, name) in DAYS {
+            match groups.last_mut() {
+                Some((_, last, h)) if *h == hours(day) => *last = name,
+                _ => groups.push((name, name, hours(day))),
+            }
+        }
+
+        let summary = groups.into_iter()
+            .filter(|(_, _, hours)| !hours.is_empty())
+            .map(|(first, last, hours)| {
+                let days = if first == last { first.to_string() } else { format!("{first}-{last}") };
+                let hours: Vec<_> = hours.iter()
+                    .map(|v| format!("{}-{}", v.open.format("%H:%M"), v.close.format("%H:%M")))
+                .collect();
+                format!("{days} {}", hours.join(","))
+            })
+            .collect::<Vec<_>>()
+        .join("; ");
+        (!summary.is_empty()).then_some(summary)
+    }
+
+    /// open / closed state at `at`, `closing_soon` is the window before closing,
+    /// by the hours alone (the routes also skip holidays for `opens_at`)
+    pub fn status<T: TimeZone>(
+        &self, mensa: &str, at: &DateTime<T>, closing_soon: Duration,
+    ) -> MensaStatus<T> {
+        let tz = at.timezone();
+        let local = |day: NaiveDate, time: NaiveTime| {
+            tz.from_local_datetime(&day.and_time(time)).earliest()
+        };
+
+        let today = at.date_naive();
+        let now = at.time();
+        let hours = self.day(mensa, today);
+
+        let current = hours.hours.iter().find(|v| v.open <= now && now < v.close);
+        if let Some(range) = current {
+            let closes_at = local(today, range.close);
+            let soon = closes_at.as_ref().is_some_and(|v| v.clone() - at.clone() <= closing_soon);
+            return MensaStatus {
+                status: if soon { OpenState::ClosingSoon } else { OpenState::Open },
+                reason: None,
+                detail: None,
+                closes_at,
+                opens_at: None,
+            }
+        }
+
+        MensaStatus {
+            status: OpenState::Closed,
+            // an exception applies
+            reason: hours.reason.map(|_| "closure".into()),
+            detail: hours.reason.map(Into::into),
+            closes_at: None,
+            opens_at: self.next_opening(mensa, today, now).and_then(|(d, t)| local(d, t)),
+        }
+    }
+
+    /// the first opening on `day` (after `after`)
+    pub fn opening_on(&self, mensa: &str, day: NaiveDate, after: Option<NaiveTime>) -> Option<NaiveTime> {
+        self.day(mensa, day).hours.iter()
+            .map(|v| v.open)
+            .filter(|open| after.is_none_or(|after| *open > after))
+        .min()
+    }
+
+    /// the next opening after `day` at `time`, looks two weeks ahead
+    fn next_opening(
+        &self, mensa: &str, day: NaiveDate, time: NaiveTime,
+    ) -> Option<(NaiveDate, NaiveTime)> {
+        const LOOKAHEAD: usize = 14;
+
+        day.iter_days().take(LOOKAHEAD).find_map(|d| {
+            self.opening_on(mensa, d, (d == day).then_some(time))
+                .map(|open| (d, open))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono_tz::{Europe::Berlin, Tz};
+
+    use super::*;
+
+    fn hours() -> OpeningHours {
+        toml::from_str(r#"
+            [default.weekly]
+            mon = ["11:00-14:30"]
+            tue = ["11:00-14:30"]
+            wed = ["11:00-14:30"]
+            thu = ["11:00-14:30"]
+            fri = ["11:00-14:30"]
+
+            [mensa.1.weekly]
+            mon = ["08:00-10:00", "11:00-15:00"]
+            tue = ["11:00-15:00"]
+            wed = ["11:00-15:00"]
+            sat = ["12:00-14:00"]
+        "#).unwrap()
+    }
+
+    fn at(s: &str) -> DateTime<Tz> {
+        let local = chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        Berlin.from_local_datetime(&local).unwrap()
+    }
+
+    fn closed(from: &str, to: &str) -> HoursException {
+        HoursException {
+            mensa: None,
+            from: from.parse().unwrap(),
+            to: Some(to.parse().unwrap()),
+            hours: Vec::new(),
+            reason: Some("break".into()),
+        }
+    }
+
+    #[test]
+    fn closing_soon_window() {
+        let hours = hours();
+        let soon = Duration::minutes(30);
+
+        // 2024-05-22 is a wednesday
+        let status = hours.status("2", &at("2024-05-22 13:59"), soon);
+        assert_eq!(status.status, OpenState::Open);
+        assert_eq!(status.closes_at, Some(at("2024-05-22 14:30")));
+
+        let status = hours.status("2", &at("2024-05-22 14:00"), soon);
+        assert_eq!(status.status, OpenState::ClosingSoon);
+
+        let status = hours.status("2", &at("2024-05-22 14:30"), soon);
+        assert_eq!(status.status, OpenState::Closed);
+        assert_eq!(status.opens_at, Some(at("2024-05-23 11:00")));
+    }
+
+    #[test]
+    fn gaps_between_ranges() {
+        let status = hours().status("1", &at("2024-05-20 10:30"), Duration::minutes(30));
+        assert_eq!(status.status, OpenState::Closed);
+        assert_eq!(status.reason, None);
+        assert_eq!(status.opens_at, Some(at("2024-05-20 11:00")));
+    }
+
+    #[test]
+    fn exceptions_override_weekly_hours() {
+        let mut hours = hours();
+        hours.add_exceptions([
+            closed("2024-05-22", "2024-05-22"),
+            HoursException {
+                mensa: Some("1".into()),
+                from: "2024-05-22".parse().unwrap(),
+                to: None,
+                hours: vec!["12:00-13:00".parse().unwrap()],
+                reason: Some("short".into()),
+            },
+        ]);
+
+        // the global closure applies to mensas without their own exception
+        let status = hours.status("2", &at("2024-05-22 12:00"), Duration::zero());
+        assert_eq!(status.status, OpenState::Closed);
+        assert_eq!(status.reason.as_deref(), Some("closure"));
+        assert_eq!(status.detail.as_deref(), Some("break"));
+        assert_eq!(status.opens_at, Some(at("2024-05-23 11:00")));
+
+        let day = hours.day("1", "2024-05-22".parse().unwrap());
+        assert_eq!(day.hours, ["12:00-13:00".parse().unwrap()]);
+        assert_eq!(day.reason, Some("short"));
+        let status = hours.status("1", &at("2024-05-22 12:30"), Duration::zero());
+        assert_eq!(status.status, OpenState::Open);
+        assert_eq!(status.closes_at, Some(at("2024-05-22 13:00")));
+    }
+
+    #[test]
+    fn next_opening_looks_two_weeks_ahead() {
+        let mut hours = hours();
+        hours.add_exceptions([closed("2024-05-22", "2024-06-03")]);
+        let status = hours.status("2", &at("2024-05-22 12:00"), Duration::zero());
+        assert_eq!(status.opens_at, Some(at("2024-06-04 11:00")));
+
+        // the first opening after that is too far away
+        let mut hours = self::hours();
+        hours.add_exceptions([closed("2024-05-22", "2024-06-05")]);
+        let status = hours.status("2", &at("2024-05-22 12:00"), Duration::zero());
+        assert_eq!(status.status, OpenState::Closed);
+        assert_eq!(status.opens_at, None);
+        let status = hours.status("2", &at("2024-06-04 12:00"), Duration::zero());
+        assert_eq!(status.opens_at, Some(at("2024-06-06 11:00")));
+    }
+
+    #[test]
+    fn summary_groups_days() {
+        let hours = hours();
+        assert_eq!(hours.summary("2").as_deref(), Some("Mo-Fr 11:00-14:30"));
+        assert_eq!(
+            hours.summary("1").as_deref(),
+            Some("Mo 08:00-10:00,11:00-15:00; Tu-We 11:00-15:00; Sa 12:00-14:00"),
+        );
+        assert_eq!(OpeningHours::default().summary("1"), None);
+    }
+}
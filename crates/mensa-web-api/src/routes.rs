@@ -1,75 +1,206 @@
 
 use axum::{
-    extract::{FromRef, Query, State},
-    http::StatusCode,
+    extract::{FromRef, Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
     routing::get,
-    Json,
     Router,
 };
 
 use chrono::{Days, NaiveDate, Weekday};
-use mensa_meal_api::{MealDay, MealPlan};
-use tokio_cron_scheduler::Job;
+use mensa_meal_api::{Format, Lang, MealDay, PriceGroup};
 
-use crate::config::Config;
+use crate::{
+    clock::Clock,
+    config::{Config, JobKind},
+    jobs::{JobRegistry, JobRun},
+    store::{self, Store},
+};
 
-use self::data::{MealCollections, MealPlanManager};
-use std::time::Instant;
+use self::{
+    data::{MealPlanError, MealPlanManager},
+    error::{ApiError, ErrorCode, Query},
+    freshness::{Fresh, Rendered},
+};
+use chrono::Utc;
+use std::path::PathBuf;
 
+mod admin;
+mod breaker;
 mod data;
+mod error;
+mod freshness;
 mod helpers;
-use helpers::*;
+mod jobs;
+mod mensas;
+mod negotiate;
+mod openapi;
+mod v1;
+use mensas::{Calendar, Directory};
 
-#[derive(Clone, FromRef)]
-struct AppState {
-    meals: MealPlanManager,
-    db: Option<mongodb::Database>,
+/// everything the router gets from the outside, tests swap these out
+pub struct Deps {
+    pub clock: Clock,
+    /// `None` runs without persistence
+    pub store: Option<Store>,
+    /// register the scheduled jobs
+    pub jobs: bool,
+    /// fetch the `prefetch` plans at startup, they are tracked either way
+    pub prefetch: bool,
 }
 
-impl AppState {
-    async fn new(config: &Config) -> Self {
-        let db = if let Some(db) = &config.db {
-            tracing::info!("connecting to db");
-            let v = connect_db(db).await;
-            tracing::info!("connected to db");
-            v
+impl Deps {
+    /// the real thing: system clock, configured db and jobs
+    pub async fn from_config(config: &Config) -> Self {
+        let store = if let Some(db) = &config.db {
+            store::connect(db).await
         } else {
             tracing::info!("no db specified");
             None
         };
 
-        let meals = MealPlanManager::new(db.as_ref().map(MealCollections::new));
-
-        let m = meals.clone();
-        register_jobs(|shed| async move {
-            // run every night at 00:01
-            shed.add(Job::new_async("0 1 0 1/1 * ? *", move |uuid, _| {
-                let m = m.clone();
-                async move {
-                    tracing::info!("fetching new data (job: {uuid:?})");
-                    let start = Instant::now();
-                    m.fetch_all().await;
-                    let took = start.elapsed();
-                    tracing::info!("fetched new data (took {took:?})");
-                }.pin()
-            })?).await?;
-
-            Ok(shed)
-        }).await;
-
-        Self { meals, db }
+        Self {
+            clock: Clock::system(config.timezone),
+            store,
+            jobs: true,
+            prefetch: true,
+        }
+    }
+}
+
+#[derive(Clone, FromRef)]
+struct AppState {
+    meals: MealPlanManager,
+    clock: Clock,
+    directory: Directory,
+    calendar: Calendar,
+    jobs: JobRegistry,
+}
+
+impl AppState {
+    async fn new(config: &Config, deps: Deps) -> Self {
+        let Deps { clock, store, jobs, prefetch: fetch_now } = deps;
+        let meals = MealPlanManager::new(config, clock.clone(), store);
+
+        if let Some(path) = &config.cache.snapshot {
+            if let Err(err) = meals.restore(path).await {
+                tracing::error!("could not restore snapshot: {err:#}");
+            }
+        }
+        if config.cache.seed_from_store {
+            meals.seed_from_store(clock.today()).await;
+        }
+
+        let calendar = Calendar::new(&config.opening_hours, &config.holidays).await;
+        let directory = Directory::new(&config.directory, calendar.hours()).await;
+
+        let mut prefetch: Vec<_> = config.prefetch.pairs()
+            .filter(|(mensa, _)| match directory.check(mensa) {
+                Ok(()) => true,
+                Err(_) => {
+                    tracing::warn!("not prefetching unknown mensa {mensa}");
+                    false
+                },
+            })
+            .map(|(m, l)| (m.to_string(), l.to_string()))
+        .collect();
+        // tracked, so every refresh includes them
+        prefetch.retain(|(mensa, lang)| {
+            let tracked = meals.track(mensa, Some(lang));
+            if !tracked {
+                tracing::warn!("not prefetching {mensa} (in {lang}), cache.max_tracked is too low");
+            }
+            tracked
+        });
+
+        let registry = JobRegistry::new(clock.clone());
+        jobs::setup(config, &clock, &meals, &registry, jobs).await;
+
+        if fetch_now && !prefetch.is_empty() {
+            tracing::info!("prefetching {} plans", prefetch.len());
+            let m = meals.clone();
+            tokio::spawn(async move { m.fetch_each(prefetch).await });
+        }
+
+        Self { meals, clock, directory, calendar, jobs: registry }
+    }
+}
+
+/// the router and what has to happen outside of requests
+pub struct App {
+    pub router: Router,
+    meals: MealPlanManager,
+    jobs: JobRegistry,
+    snapshot: Option<PathBuf>,
+}
+
+impl App {
+    /// refreshes every tracked plan, like the nightly job
+    pub async fn refresh(&self) {
+        self.meals.fetch_all().await;
+    }
+
+    /// waits for the upstream requests that are running in the background
+    pub async fn settle(&self) {
+        self.meals.settle().await;
+    }
+
+    /// runs a job from `/api/admin/jobs` now
+    pub async fn run_job(&self, name: &str) -> JobRun {
+        self.jobs.run(name).await
+    }
+
+    /// (mensa, lang) pairs that get refreshed
+    pub fn tracked(&self) -> Vec<(String, String)> {
+        self.meals.tracked()
+    }
+
+    /// saves the snapshot, call once the server stopped
+    pub async fn shutdown(&self) {
+        let Some(path) = &self.snapshot else { return };
+        if let Err(err) = self.meals.snapshot(path).await {
+            tracing::error!("could not save snapshot: {err:#}");
+        }
+    }
+}
+
+pub async fn make_app(config: &Config, deps: Deps) -> App {
+    let state = AppState::new(config, deps).await;
+    let (meals, jobs) = (state.meals.clone(), state.jobs.clone());
+
+    App {
+        router: make_router_with(state, config.server.admin_token.clone()),
+        meals,
+        jobs,
+        snapshot: config.cache.snapshot.clone(),
     }
 }
 
-pub async fn make_router(config: &Config) -> Router {
+pub async fn make_router(config: &Config, deps: Deps) -> Router {
+    make_app(config, deps).await.router
+}
+
+fn make_router_with(state: AppState, admin_token: Option<String>) -> Router {
+    let v1 = Router::new()
+        .route("/meals", get(meals))
+        .route("/meals/plan", get(meals_plan))
+        .route("/mensas", get(mensas::mensas))
+        .route("/mensas/nearby", get(mensas::nearby))
+        .route("/mensas/:id/status", get(mensas::status));
+
     Router::new()
-        .route("/api/meals", get(meals))
-        .route("/api/meals/plan", get(meals_plan))
-        .with_state(AppState::new(config).await)
+        .nest("/api/v1", v1.clone())
+        // from before versioning, answer like v1
+        .nest("/api", v1.layer(middleware::from_fn(deprecated)))
+        .merge(admin::router(admin_token))
+        .route("/api/openapi.json", get(openapi::openapi))
+        .route("/api/docs", get(openapi::docs))
+        .with_state(state)
+        .layer(middleware::from_fn(freshness::conditional))
     .fallback_service(fallback_service())
 }
 
-
 #[derive(Default, Debug, serde::Deserialize)]
 #[serde(rename_all = "snake_case")]
 enum MensaRelativeDate {
@@ -86,9 +217,9 @@ enum MensaDate {
 }
 
 impl MensaDate {
-    fn as_date(self) -> Option<NaiveDate> {
-        let today = chrono::Utc::now().date_naive();
-        Some(match self {
+    /// resolves the date relative to `today` (see [`Clock::today`])
+    fn to_date(&self, today: NaiveDate) -> Option<NaiveDate> {
+        Some(match *self {
             MensaDate::Relative(MensaRelativeDate::Today) => today,
             MensaDate::Relative(MensaRelativeDate::Yesterday) => today.pred_opt()?,
             MensaDate::Relative(MensaRelativeDate::Tomorrow) => today.succ_opt()?,
@@ -100,42 +231,189 @@ impl MensaDate {
     }
 }
 
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
 struct MensaQuery {
+    /// the upstream id, see `/api/mensas`
     mensa: String,
+    /// `de` or `en`, defaults to `Accept-Language` and then `en`
     lang: Option<String>,
+    /// defaults to today
     day: Option<MensaDate>,
+    /// overrides `Accept`, which defaults to json
+    format: Option<Format>,
+    /// whose prices the text formats show, defaults to students
+    price: Option<PriceGroup>,
 }
 
+/// every published day of a mensa
+#[utoipa::path(
+    get, path = "/api/v1/meals/plan",
+    params(MensaQuery),
+    responses(
+        (status = 200, headers(
+            ("ETag" = String), ("Last-Modified" = String), ("Cache-Control" = String),
+            ("Age" = i64), ("X-Data-Fetched-At" = String), ("Vary" = String),
+            ("Content-Language" = String), ("Warning" = String, description = "if the data is stale"),
+        ), content(
+            ("application/json" = FreshMealPlan),
+            ("text/plain" = String), ("text/markdown" = String), ("text/html" = String),
+        )),
+        (status = 304, description = "not modified"),
+        (status = 400, body = ErrorBody, description = "invalid_query, invalid_mensa_id"),
+        (status = 404, body = ErrorBody, description = "unknown_mensa"),
+        (status = 502, body = ErrorBody, description = "upstream_error"),
+        (status = 503, body = ErrorBody, description = "upstream_unavailable"),
+    ),
+)]
 async fn meals_plan(
     Query(q): Query<MensaQuery>,
     State(state): State<MealPlanManager>,
-) -> Result<Json<MealPlan>, (StatusCode, Json<String>)> {
-    let lang = q.lang.as_ref().map(String::as_str);
-    Ok(Json(state.get_plan(
+    State(clock): State<Clock>,
+    State(dir): State<Directory>,
+    State(jobs): State<JobRegistry>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let lang = negotiate::lang(q.lang.as_deref(), &headers);
+    dir.check(&q.mensa).map_err(|err| err.lang(lang))?;
+    let plan = state.get_plan(
         &q.mensa,
         lang,
-    ).await.map_err(|_| (StatusCode::NOT_FOUND, Json(format!("plan_not_found"))))?))
+    ).await
+    .map_err(|err| plan_error(&dir, &q.mensa, err).lang(lang))?;
+
+    let res = match negotiate::format(q.format, &headers) {
+        Format::Json => cacheable(plan.map(|v| v1::MealPlan::from(&v)), &state, &clock, &jobs)
+            .into_response(),
+        format => {
+            let plan = plan.map(|v| {
+                let (lang, price) = (render_lang(lang), q.price.unwrap_or_default());
+                Rendered {
+                    format,
+                    body: v.render_for(format, lang, price),
+                    etag: rendered_etag(v.content_hash(), format, lang, price),
+                }
+            });
+            cacheable(plan, &state, &clock, &jobs).into_response()
+        },
+    };
+    Ok(negotiate::localized(vary(res), lang))
 }
 
+/// the meals of a single day, or why there are none
+#[utoipa::path(
+    get, path = "/api/v1/meals",
+    params(MensaQuery),
+    responses(
+        (status = 200, headers(
+            ("ETag" = String), ("Last-Modified" = String), ("Cache-Control" = String),
+            ("Age" = i64), ("X-Data-Fetched-At" = String), ("Vary" = String),
+            ("Content-Language" = String), ("Warning" = String, description = "if the data is stale"),
+        ), content(
+            ("application/json" = FreshMealDay),
+            ("text/plain" = String), ("text/markdown" = String), ("text/html" = String),
+        )),
+        (status = 304, description = "not modified"),
+        (status = 400, body = ErrorBody, description = "invalid_query, invalid_mensa_id, invalid_date"),
+        (status = 404, body = ErrorBody, description = "unknown_mensa"),
+        (status = 502, body = ErrorBody, description = "upstream_error"),
+        (status = 503, body = ErrorBody, description = "upstream_unavailable"),
+    ),
+)]
 async fn meals(
     Query(q): Query<MensaQuery>,
     State(state): State<MealPlanManager>,
-) -> Result<Json<MealDay>, (StatusCode, Json<String>)> {
-    let lang = q.lang.as_ref().map(String::as_str);
+    State(clock): State<Clock>,
+    State(dir): State<Directory>,
+    State(calendar): State<Calendar>,
+    State(jobs): State<JobRegistry>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let lang = negotiate::lang(q.lang.as_deref(), &headers);
+    dir.check(&q.mensa).map_err(|err| err.lang(lang))?;
     let d = q.day.unwrap_or(MensaDate::Relative(MensaRelativeDate::Today));
-    Ok(Json(
-        state.get_day(&q.mensa, lang,
-            &d.as_date().ok_or_else(|| {
-                (StatusCode::BAD_REQUEST, Json(format!("invalid_date")))
-            })?,
-        ).await.ok_or_else(||
-            (StatusCode::NOT_FOUND, Json(format!("plan_not_found")))
-        )?
-    ))
+    let date = d.to_date(clock.today()).ok_or_else(|| {
+        ApiError::new(ErrorCode::InvalidDate).lang(lang)
+    })?;
+
+    // no meals, explain why
+    let day = match state.get_day(&q.mensa, lang, &date).await {
+        Ok(Some(day)) => Ok(day),
+        Ok(None) => calendar.day_state(&state, &q.mensa, lang, &date).await
+            .map(|v| Fresh::untracked(MealDay::empty(date, v))),
+        // upstream already failed for this request, it is not asked again
+        Err(err) => {
+            let plan = state.fallback_plan(&q.mensa, lang, err).await.map(|v| v.data);
+            calendar.day_state_of(plan.as_ref(), &q.mensa, &date)
+                .map(|v| Fresh::untracked(MealDay::empty(date, v)))
+        },
+    }.map_err(|err| plan_error(&dir, &q.mensa, err).lang(lang))?;
+
+    let res = match negotiate::format(q.format, &headers) {
+        Format::Json => cacheable(day.map(|v| v1::MealDay::from(&v)), &state, &clock, &jobs)
+            .into_response(),
+        format => {
+            let day = day.map(|v| {
+                let (lang, price) = (render_lang(lang), q.price.unwrap_or_default());
+                Rendered {
+                    format,
+                    body: v.render_for(format, lang, price),
+                    etag: rendered_etag(v.content_hash(), format, lang, price),
+                }
+            });
+            cacheable(day, &state, &clock, &jobs).into_response()
+        },
+    };
+    Ok(negotiate::localized(vary(res), lang))
+}
+
+/// marks the unversioned routes as deprecated, pointing to v1
+async fn deprecated(req: Request, next: Next) -> Response {
+    let successor = format!("</api/v1{}>; rel=\"successor-version\"", req.uri().path());
+    let mut res = next.run(req).await;
+
+    let headers = res.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    if let Ok(v) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, v);
+    }
+    res
+}
+
+/// lets clients cache `v` until it might change
+fn cacheable<T>(v: Fresh<T>, meals: &MealPlanManager, clock: &Clock, jobs: &JobRegistry) -> Fresh<T> {
+    let next_refresh = jobs.next_run(JobKind::Refresh, clock.now().with_timezone(&Utc));
+    let max_age = meals.max_age(v.freshness.as_ref(), next_refresh);
+    v.max_age(max_age)
+}
+
+/// what the text formats are written in
+fn render_lang(lang: Option<&str>) -> Lang {
+    lang.and_then(Lang::from_code).unwrap_or_default()
+}
+
+/// the same data rendered differently must not match
+fn rendered_etag(content_hash: u64, format: Format, lang: Lang, price: PriceGroup) -> String {
+    format!("\"{content_hash:016x}-{}-{}-{}\"", format.as_str(), lang.code(), price.as_str())
+}
+
+/// the body depends on `Accept`, caches have to know
+fn vary(mut res: Response) -> Response {
+    res.headers_mut().insert(header::VARY, HeaderValue::from_static("accept"));
+    res
+}
+
+/// nothing cached and upstream could not help
+fn plan_error(dir: &Directory, mensa: &str, err: MealPlanError) -> ApiError {
+    match err {
+        MealPlanError::UnknownMensa => dir.unknown(mensa),
+        MealPlanError::Unavailable => ApiError::new(ErrorCode::UpstreamUnavailable),
+        MealPlanError::Reqwest(_) | MealPlanError::ParsePlan(_) => ApiError::new(ErrorCode::UpstreamError),
+    }
 }
 
 fn fallback_service() -> Router {
     Router::new()
+        .fallback(|| async { ApiError::new(ErrorCode::NotFound) })
 }
 
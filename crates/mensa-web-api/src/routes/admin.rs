@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use serde::Serialize;
+
+use crate::{jobs::{JobRegistry, JobStatus}, store::PersistenceStatus};
+
+use super::{
+    breaker::BreakerStatus,
+    data::{FetchStatus, MealPlanManager},
+    error::{ApiError, ErrorCode},
+    AppState,
+};
+
+/// `/api/admin`, only there with a token
+pub(super) fn router(token: Option<String>) -> Router<AppState> {
+    let Some(token) = token else { return Router::new() };
+
+    Router::new()
+        .route("/api/admin/persistence", get(persistence))
+        .route("/api/admin/jobs", get(jobs))
+        .route("/api/admin/fetches", get(fetches))
+        .route("/api/admin/upstream", get(upstream))
+    .route_layer(middleware::from_fn_with_state(Arc::<str>::from(token), authorize))
+}
+
+/// wants `Authorization: Bearer <token>`
+async fn authorize(
+    State(token): State<Arc<str>>, req: Request, next: Next,
+) -> Response {
+    let given = req.headers().get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if given.is_some_and(|v| constant_time_eq(v.as_bytes(), token.as_bytes())) {
+        return next.run(req).await
+    }
+
+    let mut res = ApiError::new(ErrorCode::Unauthorized).into_response();
+    res.headers_mut().insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+    res
+}
+
+/// does not tell how much of the token was right by how long it took
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[derive(Debug, Serialize)]
+pub struct PersistenceResponse {
+    /// `false` when running without a db
+    enabled: bool,
+    /// days waiting for a retry, over all mensas
+    pending_days: usize,
+    mensas: Vec<PersistenceStatus>,
+}
+
+pub async fn persistence(
+    State(meals): State<MealPlanManager>,
+) -> Json<PersistenceResponse> {
+    Json(match meals.persistence() {
+        Some(p) => PersistenceResponse {
+            enabled: true,
+            pending_days: p.pending_days(),
+            mensas: p.statuses(),
+        },
+        None => PersistenceResponse {
+            enabled: false,
+            pending_days: 0,
+            mensas: Vec::new(),
+        },
+    })
+}
+
+pub async fn jobs(
+    State(jobs): State<JobRegistry>,
+) -> Json<Vec<JobStatus>> {
+    Json(jobs.statuses())
+}
+
+pub async fn fetches(
+    State(meals): State<MealPlanManager>,
+) -> Json<Vec<FetchStatus>> {
+    Json(meals.fetches())
+}
+
+pub async fn upstream(
+    State(meals): State<MealPlanManager>,
+) -> Json<BreakerStatus> {
+    Json(meals.breaker())
+}
@@ -0,0 +1,141 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::{clock::Clock, config::UpstreamConfig};
+
+/// stops asking upstream after too many failures in a row,
+/// after the cooldown a single request is let through to probe it
+#[derive(Clone)]
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    clock: Clock,
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    failures: u32,
+    opened_at: Option<DateTime<Utc>>,
+    /// the probe is running
+    probing: bool,
+    last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BreakerStatus {
+    pub state: BreakerState,
+    /// failed requests in a row
+    pub failures: u32,
+    /// until the next probe, while open
+    pub retry_in_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    /// the probe is running
+    HalfOpen,
+}
+
+/// a request that may go out, its outcome goes to [`Permit::success`] or
+/// [`Permit::failure`], dropping it (a cancelled probe) lets the next request probe
+pub struct Permit {
+    breaker: CircuitBreaker,
+    probe: bool,
+}
+
+impl Permit {
+    pub fn success(mut self) {
+        self.probe = false;
+        self.breaker.success();
+    }
+
+    pub fn failure(mut self, err: &impl ToString) {
+        self.probe = false;
+        self.breaker.failure(err);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if self.probe {
+            self.breaker.inner.lock().unwrap().probing = false;
+        }
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(upstream: &UpstreamConfig, clock: Clock) -> Self {
+        Self {
+            threshold: upstream.breaker_failures,
+            cooldown: Duration::seconds(upstream.breaker_cooldown_secs.try_into().unwrap_or(i64::MAX)),
+            clock,
+            inner: Default::default(),
+        }
+    }
+
+    /// `None` while open
+    pub fn allow(&self) -> Option<Permit> {
+        let mut inner = self.inner.lock().unwrap();
+        let probe = match inner.opened_at {
+            None => false,
+            Some(at) if self.now() - at >= self.cooldown && !inner.probing => {
+                inner.probing = true;
+                true
+            },
+            Some(_) => return None,
+        };
+        Some(Permit { breaker: self.clone(), probe })
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.clock.now().with_timezone(&Utc)
+    }
+
+    fn success(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.opened_at.is_some() {
+            tracing::info!("upstream is back, closing circuit");
+        }
+        *inner = Inner::default();
+    }
+
+    fn failure(&self, err: &impl ToString) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.failures += 1;
+        inner.last_error = Some(err.to_string());
+
+        let open = self.threshold > 0 && inner.failures >= self.threshold;
+        if inner.probing || (open && inner.opened_at.is_none()) {
+            tracing::warn!(
+                "upstream failed {} times in a row, not asking for {:?}",
+                inner.failures, self.cooldown,
+            );
+            inner.opened_at = Some(self.now());
+        }
+        inner.probing = false;
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        let inner = self.inner.lock().unwrap();
+        let state = match inner.opened_at {
+            None => BreakerState::Closed,
+            Some(_) if inner.probing => BreakerState::HalfOpen,
+            Some(_) => BreakerState::Open,
+        };
+        BreakerStatus {
+            state,
+            failures: inner.failures,
+            retry_in_ms: inner.opened_at
+                .filter(|_| state == BreakerState::Open)
+                .map(|v| (self.cooldown - (self.now() - v)).num_milliseconds().max(0) as u64),
+            last_error: inner.last_error.clone(),
+        }
+    }
+}
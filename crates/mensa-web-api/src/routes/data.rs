@@ -1,63 +1,336 @@
-use std::sync::Arc;
-
-use chrono::NaiveDate;
-use mongodb::{
-    bson::doc,
-    options::{
-        FindOneAndReplaceOptions,
-        ReplaceOptions, ReturnDocument
-    },
-    Collection,
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
-use tokio::sync::RwLock;
-use mensa_meal_api::{raw, MealDay, MealPlan, MealPlans};
 
-mod data;
-use data::*;
+use anyhow::Context;
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use futures::{future::{self, BoxFuture, Shared}, FutureExt, StreamExt};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::RwLock};
+use mensa_meal_api::{raw, MealDay, MealPlan, MealPlanParseError, MealPlans};
+
+use crate::{clock::Clock, config::{Config, UpstreamConfig}, store::{Persistence, Store}};
+
+use super::{breaker::{BreakerStatus, CircuitBreaker}, freshness::{DataSource, Fresh, Freshness}};
+
+/// cheap to clone, so a single fetch can hand it to every waiting request
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum MealPlanError {
+    #[error(transparent)]
+    Reqwest(Arc<reqwest::Error>),
+    #[error(transparent)]
+    ParsePlan(Arc<MealPlanParseError>),
+    /// upstream answered with an empty result
+    #[error("upstream does not know this mensa")]
+    UnknownMensa,
+    /// the circuit breaker is open, upstream was not asked
+    #[error("upstream is unavailable")]
+    Unavailable,
+}
+
+impl From<reqwest::Error> for MealPlanError {
+    fn from(v: reqwest::Error) -> Self { Self::Reqwest(Arc::new(v)) }
+}
+
+impl From<MealPlanParseError> for MealPlanError {
+    fn from(v: MealPlanParseError) -> Self { Self::ParsePlan(Arc::new(v)) }
+}
+
+type Inflight = Shared<BoxFuture<'static, Result<MealPlan, MealPlanError>>>;
+type Unknown = HashMap<(String, String), DateTime<Utc>>;
 
 #[derive(Clone)]
 pub struct MealPlanManager {
     client: reqwest::Client,
-    collections: Option<MealCollections>,
+    clock: Clock,
+    upstream: Arc<UpstreamConfig>,
+    breaker: CircuitBreaker,
+    persistence: Option<Persistence>,
     data: Arc<RwLock<MealPlans>>,
+    /// (mensa, lang) pairs refreshed by `fetch_all`
+    tracked: Arc<Mutex<BTreeSet<(String, String)>>>,
+    max_tracked: usize,
+    /// plans older than this are refreshed in the background
+    ttl: chrono::Duration,
+    /// (mensa, lang) pairs upstream did not know, and since when
+    unknown: Arc<Mutex<Unknown>>,
+    fetches: Arc<Mutex<BTreeMap<(String, String), FetchStatus>>>,
+    /// upstream requests currently running, keyed by (mensa, lang)
+    inflight: Arc<Mutex<HashMap<(String, String), Inflight>>>,
+}
+
+/// how refreshing a (mensa, lang) pair went
+#[derive(Debug, Clone, Serialize)]
+pub struct FetchStatus {
+    pub mensa_id: String,
+    pub lang: String,
+    pub last_attempt: DateTime<Utc>,
+    pub last_success: Option<DateTime<Utc>>,
+    /// tries the last refresh took
+    pub attempts: u32,
+    /// refreshes in a row that failed
+    pub failures: u32,
+    pub last_error: Option<String>,
+    /// when the next follow-up is due
+    pub retry_at: Option<DateTime<Utc>>,
+}
+
+/// what is written to `cache.snapshot`
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    saved_at: DateTime<Utc>,
+    tracked: BTreeSet<(String, String)>,
+    plans: MealPlans,
 }
 
 impl MealPlanManager {
-    pub fn new(collections: Option<MealCollections>) -> Self {
+    pub fn new(config: &Config, clock: Clock, store: Option<Store>) -> Self {
+        let upstream = &config.upstream;
         Self {
-            client: reqwest::Client::new(),
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_millis(upstream.timeout_ms))
+                .build()
+            .expect("http client should build"),
+            breaker: CircuitBreaker::new(upstream, clock.clone()),
+            persistence: store.map(|v| Persistence::new(v, clock.clone())),
+            clock,
+            upstream: Arc::new(upstream.clone()),
             data: Arc::new(RwLock::new(MealPlans::default())),
-            collections,
+            tracked: Default::default(),
+            max_tracked: config.cache.max_tracked,
+            ttl: chrono::Duration::seconds(config.cache.ttl_secs.try_into().unwrap_or(i64::MAX)),
+            unknown: Default::default(),
+            fetches: Default::default(),
+            inflight: Default::default(),
         }
     }
 
+    /// `false` if `cache.max_tracked` is reached
+    pub fn track(&self, mensa: &str, lang: Option<&str>) -> bool {
+        let key = (mensa.to_string(), lang.unwrap_or(MealPlans::DEFAULT_LANG).to_string());
+        let mut tracked = self.tracked.lock().unwrap();
+        if tracked.contains(&key) { return true }
+
+        if tracked.len() >= self.max_tracked {
+            tracing::warn!("already tracking {} plans, not tracking {mensa} (in {})", tracked.len(), key.1);
+            return false
+        }
+        tracked.insert(key)
+    }
+
+    pub fn tracked(&self) -> Vec<(String, String)> {
+        self.tracked.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// writes all plans to `path` (through a temporary file)
+    pub async fn snapshot(&self, path: &Path) -> anyhow::Result<()> {
+        let tracked = self.tracked.lock().unwrap().clone();
+        let snapshot = Snapshot {
+            saved_at: self.now(),
+            tracked,
+            plans: self.data.read().await.clone(),
+        };
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(&snapshot)?).await
+            .with_context(|| format!("could not write snapshot {tmp:?}"))?;
+        fs::rename(&tmp, path).await
+            .with_context(|| format!("could not move snapshot to {path:?}"))?;
+
+        tracing::info!("saved {} plans to {path:?}", snapshot.plans.len());
+        Ok(())
+    }
+
+    /// loads a snapshot written by [`Self::snapshot`], a missing file is fine
+    pub async fn restore(&self, path: &Path) -> anyhow::Result<()> {
+        if !fs::try_exists(path).await? {
+            tracing::info!("no snapshot at {path:?}, starting cold");
+            return Ok(())
+        }
+
+        let snapshot: Snapshot = serde_json::from_slice(&fs::read(path).await?)
+            .with_context(|| format!("invalid snapshot {path:?}"))?;
+        tracing::info!(
+            "restored {} plans from {path:?} (saved at {})",
+            snapshot.plans.len(), snapshot.saved_at,
+        );
+
+        for (mensa, lang) in &snapshot.tracked {
+            self.track(mensa, Some(lang));
+        }
+        let mut plans = snapshot.plans;
+        plans.retain_keys(|mensa, lang| self.is_tracked(mensa, lang));
+        *self.data.write().await = plans;
+        Ok(())
+    }
+
+    /// tracks every mensa the store has days for within the last week,
+    /// without loading those days into memory
+    pub async fn seed_from_store(&self, today: NaiveDate) {
+        let Some(store) = self.persistence.as_ref().map(Persistence::store) else { return };
+        let since = today.checked_sub_days(Days::new(7)).unwrap_or(today);
+        let until = today.checked_add_days(Days::new(365)).unwrap_or(today);
+
+        let mensas = match store.list_mensas().await {
+            Ok(v) => v,
+            Err(err) => {
+                tracing::error!("could not list stored mensas: {err}");
+                return
+            },
+        };
+
+        let mut seeded = 0;
+        for mensa in mensas {
+            match store.history(&mensa.mensa_id, Some(&mensa.lang), &until, 1).await {
+                Ok(v) if v.first().is_some_and(|v| v.meal.date >= since) => {
+                    if self.track(&mensa.mensa_id, Some(&mensa.lang)) { seeded += 1 }
+                },
+                Ok(_) => {},
+                Err(err) => tracing::error!("could not read {} from store: {err}", mensa.mensa_id),
+            }
+        }
+        tracing::info!("tracking {seeded} mensas from the store");
+    }
+
+    /// `None` when running without a db
+    pub fn persistence(&self) -> Option<&Persistence> {
+        self.persistence.as_ref()
+    }
+
+    /// from memory or upstream, falls back to the stored days when upstream fails
     pub async fn get_plan(
         &self, mensa: &str, lang: Option<&str>,
-    ) -> Result<MealPlan, MealPlanError> {
+    ) -> Result<Fresh<MealPlan>, MealPlanError> {
         let data = self.data.read().await;
 
         if let Some(plan) = data.get(mensa, lang) {
-            Ok(plan.clone())
-        } else {
-            drop(data);
-            self.fetch_plan(mensa, lang).await
+            self.revalidate(mensa, lang, plan);
+            return Ok(Fresh::new(plan.clone(), self.freshness(plan)))
+        }
+        drop(data);
+
+        match self.fetch_plan(mensa, lang).await {
+            Ok(plan) => Ok(Fresh::new(plan.clone(), self.freshness(&plan))),
+            Err(err) => self.fallback_plan(mensa, lang, err).await,
+        }
+    }
+
+    /// the stored plan after upstream failed with `err`, without asking it again
+    pub async fn fallback_plan(
+        &self, mensa: &str, lang: Option<&str>, err: MealPlanError,
+    ) -> Result<Fresh<MealPlan>, MealPlanError> {
+        if let MealPlanError::UnknownMensa = err { return Err(err) }
+
+        match self.stored_plan(mensa, lang).await {
+            Some((plan, fetched_at)) => {
+                tracing::warn!("serving stored plan for {mensa}, upstream failed: {err}");
+                Ok(Fresh::new(plan, Freshness::store(fetched_at, self.now(), true)))
+            },
+            None => Err(err),
+        }
+    }
+
+    /// the stored days from a week ago on (not kept in memory)
+    /// and when the oldest of them was fetched
+    async fn stored_plan(
+        &self, mensa: &str, lang: Option<&str>,
+    ) -> Option<(MealPlan, Option<DateTime<Utc>>)> {
+        let store = self.persistence.as_ref()?.store();
+        let today = self.clock.today();
+        let since = today.checked_sub_days(Days::new(7)).unwrap_or(today);
+        let until = today.checked_add_days(Days::new(365)).unwrap_or(today);
+
+        let days = match store.get_range(mensa, lang, &since, &until).await {
+            Ok(v) if !v.is_empty() => v,
+            Ok(_) => return None,
+            Err(err) => {
+                tracing::error!("could not read {mensa} from store: {err}");
+                return None
+            },
+        };
+
+        let lang = lang.unwrap_or(MealPlans::DEFAULT_LANG);
+        let name = store.list_mensas().await.ok()
+            .and_then(|v| v.into_iter().find(|v| v.mensa_id == mensa && v.lang == lang))
+        .map_or_else(String::new, |v| v.name);
+
+        // `None` sorts first, so one day without a time is enough
+        let fetched_at = days.iter().map(|v| v.fetched_at).min().flatten();
+        let mut plan = MealPlan::new(name);
+        for day in days {
+            plan.add_day(day.meal.date, day.meal);
         }
+        Some((plan, fetched_at))
     }
 
+    /// fetches the plan from upstream, concurrent calls for the same
+    /// (mensa, lang) share a single request and its result
     pub async fn fetch_plan(
         &self,
         mensa: &str,
         lang: Option<&str>,
     ) -> Result<MealPlan, MealPlanError> {
-        const DATA_URL: &str = r#"https://app2022.stw.berlin/api/getdata.php"#;
+        let key = (mensa.to_string(), lang.unwrap_or(MealPlans::DEFAULT_LANG).to_string());
+        if self.is_unknown(&key) { return Err(MealPlanError::UnknownMensa) }
+
+        self.inflight_fetch(key).await
+    }
+
+    /// the running request for `key`, or a new one, which runs to the end
+    /// even if nobody waits for it anymore (like a probe of the circuit breaker)
+    fn inflight_fetch(&self, key: (String, String)) -> Inflight {
+        self.inflight.lock().unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                let m = self.clone();
+                let task = tokio::spawn(async move {
+                    let res = m.fetch_upstream(&key.0, &key.1).await;
+                    m.inflight.lock().unwrap().remove(&key);
+                    res
+                });
+                async move { task.await.expect("upstream fetch panicked") }.boxed().shared()
+            })
+        .clone()
+    }
+
+    /// waits for the upstream requests that are running,
+    /// like the refreshes of stale plans
+    pub async fn settle(&self) {
+        let running: Vec<_> = self.inflight.lock().unwrap().values().cloned().collect();
+        futures::future::join_all(running).await;
+    }
+
+    async fn fetch_upstream(
+        &self, mensa: &str, lang: &str,
+    ) -> Result<MealPlan, MealPlanError> {
+        let Some(permit) = self.breaker.allow() else { return Err(MealPlanError::Unavailable) };
 
-        let data: raw::ApiResult = self.client.get(DATA_URL)
-            .query(&raw::ApiQuery::new(mensa, lang))
-            .send().await?
-        .json().await?;
+        let res = async {
+            self.client.get(&self.upstream.url)
+                .query(&raw::ApiQuery::new(mensa, Some(lang)))
+                .send().await?
+                .error_for_status()?
+            .json::<raw::ApiResult>().await
+        }.await;
+        match &res {
+            Ok(_) => permit.success(),
+            Err(err) => permit.failure(err),
+        }
+        let data = res?;
 
-        let plan = MealPlan::try_from(data)?;
-        self.store_plan(mensa.into(), lang.map(ToOwned::to_owned), plan.clone()).await;
+        if data.result.is_empty() && data.mensaname.is_empty() {
+            tracing::info!("upstream does not know mensa {mensa} (in {lang})");
+            self.remember_unknown(mensa, lang).await;
+            return Err(MealPlanError::UnknownMensa)
+        }
+
+        let mut plan = MealPlan::try_from(data)?;
+        plan.set_fetched(&self.upstream.url, self.now());
+        self.store_plan(mensa.into(), Some(lang.into()), plan.clone()).await;
 
         Ok(plan)
     }
@@ -66,132 +339,284 @@ impl MealPlanManager {
         &self, mensa_id: String, lang: Option<String>,
         plan: MealPlan,
     ) {
-        self.data.write().await
-            .insert(&mensa_id, lang.as_ref().map(|v| v.as_str()), plan.clone());
-
-        if let Some(collections) = self.collections.clone() {
-            tokio::spawn({
-                async move {
-                    if let Err(err) = collections.clone().store_plan(mensa_id, lang, &plan).await {
-                        tracing::error!("could not store data: {err}");
-                    }
-                }
+        // beyond the limit plans are only persisted, not kept
+        if self.track(&mensa_id, lang.as_deref()) {
+            self.data.write().await
+                .insert(&mensa_id, lang.as_deref(), plan.clone());
+        }
+
+        // failed writes are queued, see `Persistence::retry`
+        if let Some(persistence) = self.persistence.clone() {
+            tokio::spawn(async move {
+                persistence.write(&mensa_id, lang.as_deref(), plan).await;
             });
         }
     }
 
-    pub async fn fetch_all(&self) {
-        let mensas: Vec<_> = self.data.read().await
-            .mensas()
-            .map(|(m, l)| (m.to_string(), l.to_string()))
-        .collect();
+    /// returns how many plans could not be fetched
+    pub async fn fetch_all(&self) -> usize {
+        self.fetch_each(self.tracked()).await
+    }
+
+    /// fetches the given (mensa, lang) pairs (`upstream.concurrency` at a time),
+    /// schedules follow-ups for the failed ones and returns how many failed
+    pub async fn fetch_each(&self, mensas: Vec<(String, String)>) -> usize {
+        let failed = self.fetch_pairs(mensas).await;
+        if !failed.is_empty() {
+            self.follow_up(failed.clone());
+        }
+        failed.len()
+    }
+
+    /// returns the pairs that still failed after all attempts
+    async fn fetch_pairs(&self, mensas: Vec<(String, String)>) -> Vec<(String, String)> {
+        futures::stream::iter(mensas)
+            .map(|(mensa, lang)| async move {
+                let res = self.fetch_with_retry(&mensa, &lang).await;
+                res.is_err().then_some((mensa, lang))
+            })
+            .buffer_unordered(self.upstream.concurrency)
+            .filter_map(future::ready)
+        .collect().await
+    }
+
+    async fn fetch_with_retry(&self, mensa: &str, lang: &str) -> Result<(), MealPlanError> {
+        let mut attempt = 0;
+        let res = loop {
+            attempt += 1;
+            match self.fetch_plan(mensa, Some(lang)).await {
+                Ok(_) => break Ok(()),
+                Err(err @ (MealPlanError::UnknownMensa | MealPlanError::Unavailable)) => break Err(err),
+                Err(err) if attempt >= self.upstream.attempts => break Err(err),
+                Err(err) => {
+                    let delay = backoff(&self.upstream, attempt);
+                    tracing::warn!(
+                        "could not fetch mensa {mensa} (in {lang}), try {attempt} of {}, \
+                        retrying in {delay:?}: {err}",
+                        self.upstream.attempts,
+                    );
+                    tokio::time::sleep(delay).await;
+                },
+            }
+        };
+
+        match &res {
+            Ok(()) => tracing::info!("updated plan for {mensa} in {lang}"),
+            Err(err) => tracing::error!("could not fetch mensa {mensa} (in {lang}): {err}"),
+        }
+        self.record_fetch(mensa, lang, attempt, res.as_ref().err());
+        res
+    }
+
+    fn record_fetch(&self, mensa: &str, lang: &str, attempts: u32, err: Option<&MealPlanError>) {
+        let now = self.now();
+        let mut fetches = self.fetches.lock().unwrap();
+        let status = fetches.entry((mensa.into(), lang.into())).or_insert_with(|| FetchStatus {
+            mensa_id: mensa.into(),
+            lang: lang.into(),
+            last_attempt: now,
+            last_success: None,
+            attempts: 0,
+            failures: 0,
+            last_error: None,
+            retry_at: None,
+        });
+
+        status.last_attempt = now;
+        status.attempts = attempts;
+        status.retry_at = None;
+        match err {
+            None => {
+                status.last_success = Some(now);
+                status.failures = 0;
+                status.last_error = None;
+            },
+            Some(err) => {
+                status.failures += 1;
+                status.last_error = Some(err.to_string());
+            },
+        }
+    }
+
+    /// tries `failed` again later, up to `upstream.follow_ups` times
+    fn follow_up(&self, mut failed: Vec<(String, String)>) {
+        let (rounds, delay) = (self.upstream.follow_ups, Duration::from_secs(self.upstream.follow_up_secs));
+        if rounds == 0 { return }
+
+        let m = self.clone();
+        tokio::spawn(async move {
+            for round in 1..=rounds {
+                let at = m.now() + delay;
+                for key in &failed {
+                    if let Some(v) = m.fetches.lock().unwrap().get_mut(key) { v.retry_at = Some(at) }
+                }
+
+                tracing::info!("retrying {} failed plans in {delay:?} ({round} of {rounds})", failed.len());
+                tokio::time::sleep(delay).await;
+                failed = m.fetch_pairs(failed).await;
+                if failed.is_empty() { break }
+            }
+        });
+    }
+
+    fn is_tracked(&self, mensa: &str, lang: &str) -> bool {
+        self.tracked.lock().unwrap().contains(&(mensa.into(), lang.into()))
+    }
+
+    fn is_unknown(&self, key: &(String, String)) -> bool {
+        let since = self.now() - self.unknown_ttl();
+        self.unknown.lock().unwrap().get(key).is_some_and(|v| *v > since)
+    }
 
-        for (mensa, lang) in mensas {
-            if let Err(err) = self.fetch_plan(&mensa, Some(&lang)).await {
-                tracing::error!("could not fetch mensa {mensa} (in {lang}): {err}");
-            } else {
-                tracing::info!("updated plan for {mensa} in {lang}");
+    /// stops tracking the mensa in `lang`, forgets expired entries
+    /// (or the oldest one) so this stays within `cache.max_tracked`
+    async fn remember_unknown(&self, mensa: &str, lang: &str) {
+        {
+            let now = self.now();
+            let since = now - self.unknown_ttl();
+            let mut unknown = self.unknown.lock().unwrap();
+            unknown.retain(|_, v| *v > since);
+            if unknown.len() >= self.max_tracked {
+                let oldest = unknown.iter().min_by_key(|(_, v)| **v).map(|(k, _)| k.clone());
+                if let Some(key) = oldest { unknown.remove(&key); }
             }
+            unknown.insert((mensa.into(), lang.into()), now);
+        }
+
+        self.tracked.lock().unwrap().retain(|(m, l)| (m.as_str(), l.as_str()) != (mensa, lang));
+        self.data.write().await.retain_keys(|m, l| (m, l) != (mensa, lang));
+    }
+
+    fn unknown_ttl(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.upstream.unknown_ttl_secs.try_into().unwrap_or(i64::MAX))
+    }
+
+    fn now(&self) -> DateTime<Utc> {
+        self.clock.now().with_timezone(&Utc)
+    }
+
+    /// how old `plan` is, plans from before this was tracked count as stale
+    pub fn freshness(&self, plan: &MealPlan) -> Freshness {
+        let age = plan.fetched_at().map(|v| self.now() - v);
+        Freshness {
+            source: DataSource::Upstream,
+            upstream: plan.source().map(Into::into),
+            fetched_at: plan.fetched_at(),
+            age: age.map(|v| v.num_seconds()),
+            stale: age.is_none_or(|v| v > self.ttl),
         }
     }
 
+    /// how long clients may cache data with this freshness: until
+    /// `next_refresh`, or until it gets stale if that is sooner
+    pub fn max_age(&self, freshness: Option<&Freshness>, next_refresh: Option<DateTime<Utc>>) -> i64 {
+        if freshness.is_some_and(|v| v.stale) { return 0 }
+
+        let expires = freshness.and_then(|v| v.fetched_at).map(|v| v + self.ttl);
+        [expires, next_refresh].into_iter()
+            .flatten()
+            .min()
+        .map_or(0, |v| (v - self.now()).num_seconds().max(0))
+    }
+
+    /// refreshes `plan` in the background if it is stale,
+    /// concurrent calls share the fetch (see [`Self::fetch_plan`])
+    fn revalidate(&self, mensa: &str, lang: Option<&str>, plan: &MealPlan) {
+        if !self.freshness(plan).stale { return }
+
+        tracing::info!("plan for {mensa} is stale, refreshing");
+        let key = (mensa.to_string(), lang.unwrap_or(MealPlans::DEFAULT_LANG).to_string());
+        // registered before the stale plan is answered, see `settle`
+        let fetch = self.inflight_fetch(key);
+        let mensa = mensa.to_string();
+        tokio::spawn(async move {
+            if let Err(err) = fetch.await {
+                tracing::warn!("could not refresh stale plan for {mensa}: {err}");
+            }
+        });
+    }
+
+    pub fn breaker(&self) -> BreakerStatus {
+        self.breaker.status()
+    }
+
+    /// the outcome of the last refresh of every pair
+    pub fn fetches(&self) -> Vec<FetchStatus> {
+        self.fetches.lock().unwrap().values().cloned().collect()
+    }
+
+    /// drops plans without days from `today` on, they are fetched again when
+    /// requested (or on the next refresh), returns how many
+    pub async fn drop_past(&self, today: NaiveDate) -> usize {
+        let mut data = self.data.write().await;
+        let before = data.len();
+        data.retain(|v| v.date_range().is_some_and(|(_, last)| last >= today));
+        before - data.len()
+    }
+
+    /// the day from memory, refreshing the plan if it is stale
+    /// (also when it does not have the day, upstream might have published it since)
     pub async fn get_day_internal(
         &self, mensa: &str, lang: Option<&str>,
         day: &NaiveDate,
-    ) -> Option<MealDay> {
-        self.data.read().await
-            .get(mensa, lang)
-            .and_then(|v| v.get_day_internal(day))
-        .cloned()
+    ) -> Option<Fresh<MealDay>> {
+        let data = self.data.read().await;
+        let plan = data.get(mensa, lang)?;
+        self.revalidate(mensa, lang, plan);
+        let v = plan.get_day_internal(day)?;
+        Some(Fresh::new(v.clone(), self.freshness(plan)))
     }
 
+    /// looks in memory, then upstream (if the plan is not loaded yet),
+    /// then falls back to the store (for past days or when upstream fails),
+    /// `Err` if upstream failed and the store does not have the day either
     pub async fn get_day(
         &self, mensa_id: &str, lang: Option<&str>,
         day: &NaiveDate,
-    ) -> Option<MealDay> {
+    ) -> Result<Option<Fresh<MealDay>>, MealPlanError> {
         if let Some(v) = self.get_day_internal(mensa_id, lang, day).await {
-            Some(v)
-        } else if let Some(collections) = &self.collections {
-            collections.get_day(mensa_id, lang, day).await.ok().flatten()
-        } else {
-            self.fetch_plan(mensa_id, lang).await
-                .ok()
-            .and_then(|v| v.get_day_internal(day).cloned())
+            return Ok(Some(v))
         }
-    }
-}
-
-#[derive(Clone)]
-pub struct MealCollections {
-    meals: Collection<MensaMealDay>,
-    mensas: Collection<MensaData>,
-}
 
-impl MealCollections {
-    pub fn new(db: &mongodb::Database) -> Self {
-        Self {
-            meals: db.collection("meals"),
-            mensas: db.collection("mensas"),
+        let loaded = self.data.read().await.get(mensa_id, lang).is_some();
+        let mut failed = None;
+        if !loaded {
+            match self.fetch_plan(mensa_id, lang).await {
+                Ok(plan) => if let Some(v) = plan.get_day_internal(day) {
+                    return Ok(Some(Fresh::new(v.clone(), self.freshness(&plan))))
+                },
+                Err(err) => {
+                    tracing::warn!("could not fetch {mensa_id}: {err}");
+                    failed = Some(err);
+                },
+            }
         }
-    }
-
-    async fn get_day(
-        &self, mensa_id: &str, lang: Option<&str>,
-        day: &NaiveDate,
-    ) -> mongodb::error::Result<Option<MealDay>> {
-        let Some(mensa_id) = self.mensas.find_one(doc! {
-            "id": mensa_id,
-            "lang": lang,
-        }, None).await?.and_then(|v| v._id) else {
-            return Ok(None);
-        };
 
-        Ok(self.meals.find_one(doc! {
-            "mensa_record_id": mensa_id,
-            "meal": {
-                "date": day.to_string(),
+        // only stale if upstream should have had it
+        let stale = failed.as_ref().is_some_and(|v| !matches!(v, MealPlanError::UnknownMensa));
+        let stored = match self.persistence.as_ref().map(Persistence::store) {
+            Some(store) => match store.get_day(mensa_id, lang, day).await {
+                Ok(v) => v.map(|v| Fresh::new(v.meal, Freshness::store(v.fetched_at, self.now(), stale))),
+                Err(err) => {
+                    tracing::error!("could not read {mensa_id} ({day}) from store: {err}");
+                    None
+                },
             },
-        }, None).await?.map(|v| v.meal))
-    }
-
-    async fn store_plan(
-        &self, mensa_id: String, lang: Option<String>,
-        plan: &MealPlan,
-    ) -> mongodb::error::Result<()> {
-        let lang = lang.unwrap_or_else(|| format!("en"));
-        let mensa = self.mensas.find_one_and_replace(doc! {
-            "mensa_id": &mensa_id,
-            "lang": &lang,
-        }, MensaData {
-            _id: None,
-            mensa_id, lang,
-            name: plan.mensa().into(),
-        }, Some(
-            FindOneAndReplaceOptions::builder()
-                .upsert(true)
-                .return_document(ReturnDocument::After)
-            .build()
-        )).await?.expect("upsert should always create");
-
-        let Some(mensa_record_id) = mensa._id else {
-            tracing::error!("mensa does not have _id, could not store plan");
-            return Ok(())
+            None => None,
         };
 
-        for meal in plan.days() {
-            self.meals.replace_one(doc! {
-                "mensa_record_id": mensa_record_id,
-                "meal.date": meal.date.to_string(),
-            }, MensaMealDay {
-                mensa_record_id,
-                meal: meal.clone(),
-            }, ReplaceOptions::builder()
-                .upsert(true)
-            .build()).await?;
+        match (stored, failed) {
+            (Some(v), _) => Ok(Some(v)),
+            (None, Some(err)) => Err(err),
+            (None, None) => Ok(None),
         }
-
-        Ok(())
     }
 }
 
-
+/// exponential, capped at `backoff_max_ms`, randomly shortened by up to half
+fn backoff(upstream: &UpstreamConfig, attempt: u32) -> Duration {
+    let max = upstream.backoff_ms
+        .saturating_mul(1 << (attempt - 1).min(20))
+    .min(upstream.backoff_max_ms);
+    Duration::from_millis(rand::rng().random_range(max / 2..=max))
+}
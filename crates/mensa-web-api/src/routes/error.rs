@@ -0,0 +1,205 @@
+use axum::{
+    async_trait,
+    extract::{rejection::{PathRejection, QueryRejection}, FromRequestParts},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use super::negotiate;
+
+/// what every endpoint answers with when something goes wrong:
+/// `{ "error": { "code", "message", "details" } }`
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    code: ErrorCode,
+    /// `de` or `en`, for the message
+    lang: Option<String>,
+    details: Option<Value>,
+}
+
+/// stable, clients match on these
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// the query string could not be parsed
+    InvalidQuery,
+    /// a part of the path could not be parsed
+    InvalidPath,
+    InvalidDate,
+    InvalidMensaId,
+    UnknownMensa,
+    InvalidCoordinates,
+    InvalidFilter,
+    /// upstream failed and nothing is cached
+    UpstreamError,
+    /// the circuit breaker is open and nothing is cached
+    UpstreamUnavailable,
+    NotFound,
+    /// `/api/admin` without the admin token
+    Unauthorized,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> StatusCode {
+        match self {
+            Self::InvalidQuery
+            | Self::InvalidPath
+            | Self::InvalidDate
+            | Self::InvalidMensaId
+            | Self::InvalidCoordinates
+            | Self::InvalidFilter => StatusCode::BAD_REQUEST,
+            Self::UnknownMensa | Self::NotFound => StatusCode::NOT_FOUND,
+            Self::UpstreamError => StatusCode::BAD_GATEWAY,
+            Self::UpstreamUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+        }
+    }
+
+    /// english unless `lang` is `de`
+    pub fn message(self, lang: Option<&str>) -> &'static str {
+        let de = lang == Some("de");
+        match self {
+            Self::InvalidQuery => if de {
+                "Die Anfrageparameter sind ungültig."
+            } else { "The query parameters are invalid." },
+            Self::InvalidPath => if de {
+                "Der Pfad ist ungültig."
+            } else { "The path is invalid." },
+            Self::InvalidDate => if de {
+                "Das Datum ist ungültig."
+            } else { "The date is invalid." },
+            Self::InvalidMensaId => if de {
+                "Mensa-IDs bestehen aus bis zu 16 Buchstaben und Ziffern."
+            } else { "Mensa ids consist of up to 16 letters and digits." },
+            Self::UnknownMensa => if de {
+                "Es gibt keine Mensa mit dieser ID."
+            } else { "There is no mensa with this id." },
+            Self::InvalidCoordinates => if de {
+                "Die Koordinaten liegen außerhalb des gültigen Bereichs."
+            } else { "The coordinates are out of range." },
+            Self::InvalidFilter => if de {
+                "Der Filter enthält unbekannte Merkmale oder Allergene."
+            } else { "The filter contains unknown attributes or allergens." },
+            Self::UpstreamError => if de {
+                "Das Studierendenwerk hat nicht richtig geantwortet, bitte später erneut versuchen."
+            } else { "The Studierendenwerk did not answer properly, please try again later." },
+            Self::UpstreamUnavailable => if de {
+                "Das Studierendenwerk ist gerade nicht erreichbar, bitte später erneut versuchen."
+            } else { "The Studierendenwerk is unavailable right now, please try again later." },
+            Self::NotFound => if de {
+                "Diesen Endpunkt gibt es nicht."
+            } else { "This endpoint does not exist." },
+            Self::Unauthorized => if de {
+                "Dafür ist ein gültiges Token nötig."
+            } else { "This needs a valid token." },
+        }
+    }
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode) -> Self {
+        Self { code, lang: None, details: None }
+    }
+
+    pub fn lang(mut self, lang: Option<&str>) -> Self {
+        self.lang = lang.map(Into::into);
+        self
+    }
+
+    pub fn details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    error: ErrorContent,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorContent {
+    code: ErrorCode,
+    /// in the requested `lang`
+    message: &'static str,
+    /// depends on the code, like the `suggestions` for `unknown_mensa`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    details: Option<Value>,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: ErrorContent {
+                code: self.code,
+                message: self.code.message(self.lang.as_deref()),
+                details: self.details,
+            },
+        };
+        // the message is german or english, whatever else was asked for
+        let lang = self.lang.as_deref().filter(|v| *v == "de");
+        negotiate::localized((self.code.status(), Json(body)).into_response(), lang)
+    }
+}
+
+/// like axum's `Query`, but rejects with an [`ApiError`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Query<T>(pub T);
+
+#[derive(Deserialize)]
+struct LangQuery {
+    lang: Option<String>,
+}
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        axum::extract::Query::<T>::from_request_parts(parts, state).await
+            .map(|v| Self(v.0))
+        .map_err(|err: QueryRejection| {
+            ApiError::new(ErrorCode::InvalidQuery)
+                .lang(negotiate::lang(query_lang(parts).as_deref(), &parts.headers))
+                .details(serde_json::json!({ "reason": err.body_text() }))
+        })
+    }
+}
+
+/// like axum's `Path`, but rejects with an [`ApiError`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        axum::extract::Path::<T>::from_request_parts(parts, state).await
+            .map(|v| Self(v.0))
+        .map_err(|err: PathRejection| {
+            ApiError::new(ErrorCode::InvalidPath)
+                .lang(negotiate::lang(query_lang(parts).as_deref(), &parts.headers))
+                .details(serde_json::json!({ "reason": err.body_text() }))
+        })
+    }
+}
+
+/// `?lang=`, even if the rest of the query is broken
+fn query_lang(parts: &Parts) -> Option<String> {
+    axum::extract::Query::<LangQuery>::try_from_uri(&parts.uri).ok()
+        .and_then(|v| v.0.lang)
+}
@@ -0,0 +1,199 @@
+use axum::{
+    extract::Request,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use mensa_meal_api::Format;
+use super::v1::{MealDay, MealPlan};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+pub const X_DATA_FETCHED_AT: HeaderName = HeaderName::from_static("x-data-fetched-at");
+
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// where the served data is from, when it was fetched and how old it is are only
+/// in the `X-Data-Fetched-At`, `Last-Modified`, `Age` and `Warning` headers,
+/// so the body only changes with the data (and its `ETag` can be strong)
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Freshness {
+    pub source: DataSource,
+    /// the upstream url, if it came from there
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream: Option<String>,
+    #[serde(skip)]
+    pub fetched_at: Option<DateTime<Utc>>,
+    /// in seconds
+    #[serde(skip)]
+    pub age: Option<i64>,
+    /// older than `cache.ttl_secs` (a refresh is on its way),
+    /// or served from the store because upstream failed
+    #[serde(skip)]
+    pub stale: bool,
+}
+
+impl Freshness {
+    /// `fetched_at` is `None` for days stored before it was kept
+    pub fn store(fetched_at: Option<DateTime<Utc>>, now: DateTime<Utc>, stale: bool) -> Self {
+        Self {
+            source: DataSource::Store,
+            upstream: None,
+            fetched_at,
+            age: fetched_at.map(|v| (now - v).num_seconds()),
+            stale,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DataSource {
+    Upstream,
+    /// the db, when upstream does not have the day (anymore)
+    Store,
+}
+
+/// what the `ETag` is made of
+pub trait ContentHash {
+    fn content_hash(&self) -> u64;
+}
+
+/// a response body together with its freshness, which is also put
+/// into the `Age`, `X-Data-Fetched-At`, `Last-Modified` and `Warning` headers
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[aliases(FreshMealDay = Fresh<MealDay>, FreshMealPlan = Fresh<MealPlan>)]
+pub struct Fresh<T> {
+    #[serde(flatten)]
+    pub data: T,
+    pub freshness: Option<Freshness>,
+    /// for `Cache-Control`, in seconds
+    #[serde(skip)]
+    max_age: Option<i64>,
+}
+
+impl<T> Fresh<T> {
+    pub fn new(data: T, freshness: Freshness) -> Self {
+        Self { data, freshness: Some(freshness), max_age: None }
+    }
+
+    /// for data that was not fetched, like closed days
+    pub fn untracked(data: T) -> Self {
+        Self { data, freshness: None, max_age: None }
+    }
+
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Fresh<U> {
+        Fresh { data: f(self.data), freshness: self.freshness, max_age: self.max_age }
+    }
+
+    /// lets clients cache the response for `secs`
+    pub fn max_age(mut self, secs: i64) -> Self {
+        self.max_age = Some(secs);
+        self
+    }
+}
+
+impl<T: Serialize + ContentHash> IntoResponse for Fresh<T> {
+    fn into_response(self) -> Response {
+        // strong, the body has nothing but the data and where it is from
+        let source = match self.freshness.as_ref().map(|v| v.source) {
+            Some(DataSource::Upstream) => "upstream",
+            Some(DataSource::Store) => "store",
+            None => "untracked",
+        };
+        let etag = format!("\"{:016x}-{source}\"", self.data.content_hash());
+        let (freshness, max_age) = (self.freshness.clone(), self.max_age);
+        let mut res = Json(self).into_response();
+        set_headers(res.headers_mut(), &etag, freshness.as_ref(), max_age);
+        res
+    }
+}
+
+/// a body that [`mensa_meal_api::Format`] rendered for people
+#[derive(Debug, Clone)]
+pub struct Rendered {
+    pub format: Format,
+    pub body: String,
+    /// of the data and whatever else went into `body`, like the price group
+    pub etag: String,
+}
+
+impl IntoResponse for Fresh<Rendered> {
+    fn into_response(self) -> Response {
+        let Rendered { format, body, etag } = self.data;
+        let mut res = ([(header::CONTENT_TYPE, format.mime())], body).into_response();
+        set_headers(res.headers_mut(), &etag, self.freshness.as_ref(), self.max_age);
+        res
+    }
+}
+
+fn set_headers(headers: &mut HeaderMap, etag: &str, freshness: Option<&Freshness>, max_age: Option<i64>) {
+    if let Ok(v) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, v);
+    }
+    if let Some(secs) = max_age {
+        let v = format!("public, max-age={}", secs.max(0));
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_str(&v).expect("valid header"));
+    }
+    if let Some(age) = freshness.and_then(|v| v.age) {
+        headers.insert(header::AGE, age.max(0).into());
+    }
+    if freshness.is_some_and(|v| v.stale) {
+        headers.insert(header::WARNING, HeaderValue::from_static("110 - \"Response is Stale\""));
+    }
+    if let Some(at) = freshness.and_then(|v| v.fetched_at) {
+        if let Ok(v) = HeaderValue::from_str(&at.to_rfc3339()) {
+            headers.insert(X_DATA_FETCHED_AT, v);
+        }
+        if let Ok(v) = HeaderValue::from_str(&at.format(HTTP_DATE).to_string()) {
+            headers.insert(header::LAST_MODIFIED, v);
+        }
+    }
+}
+
+/// answers `If-None-Match` and `If-Modified-Since` with a 304
+/// for responses that have an `ETag` or `Last-Modified`
+pub async fn conditional(req: Request, next: Next) -> Response {
+    let cacheable = matches!(*req.method(), Method::GET | Method::HEAD);
+    let if_none_match = req.headers().get(header::IF_NONE_MATCH).cloned();
+    let if_modified_since = http_date(req.headers(), header::IF_MODIFIED_SINCE);
+
+    let res = next.run(req).await;
+    if !cacheable || res.status() != StatusCode::OK { return res }
+
+    let headers = res.headers();
+    // `If-Modified-Since` is ignored if there is an `If-None-Match`
+    let not_modified = match if_none_match {
+        Some(tags) => headers.get(header::ETAG).is_some_and(|etag| etag_matches(&tags, etag)),
+        None => if_modified_since.zip(http_date(headers, header::LAST_MODIFIED))
+            .is_some_and(|(since, modified)| modified <= since),
+    };
+    if !not_modified { return res }
+
+    let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+    for name in [
+        header::ETAG, header::LAST_MODIFIED, header::CACHE_CONTROL, header::AGE,
+        header::VARY, header::CONTENT_LANGUAGE, header::WARNING, X_DATA_FETCHED_AT,
+    ] {
+        if let Some(v) = headers.get(&name) {
+            not_modified.headers_mut().insert(name, v.clone());
+        }
+    }
+    not_modified
+}
+
+/// weak comparison, like `If-None-Match` wants
+fn etag_matches(tags: &HeaderValue, etag: &HeaderValue) -> bool {
+    let (Ok(tags), Ok(etag)) = (tags.to_str(), etag.to_str()) else { return false };
+    let etag = etag.trim_start_matches("W/");
+    tags.split(',')
+        .map(str::trim)
+    .any(|v| v == "*" || v.trim_start_matches("W/") == etag)
+}
+
+fn http_date(headers: &HeaderMap, name: HeaderName) -> Option<DateTime<Utc>> {
+    let v = headers.get(name)?.to_str().ok()?;
+    Some(NaiveDateTime::parse_from_str(v, HTTP_DATE).ok()?.and_utc())
+}
@@ -1,47 +1,49 @@
-use std::{future::Future, pin::Pin};
+use std::{collections::HashSet, future::Future, hash::Hash};
 
-use mongodb::options::ClientOptions;
+use mensa_meal_api::MealFilter;
+use serde::{de::{value::StrDeserializer, IntoDeserializer}, Deserialize};
 use tokio_cron_scheduler::{JobScheduler, JobSchedulerError};
 
-use crate::config::DbConfig;
-
-pub trait Pinable: Sized {
-    fn pin(self) -> Pin<Box<Self>> { Box::pin(self) }
-}
-
-impl<F: Future> Pinable for F {}
-
 pub async fn register_jobs<'a, F>(
     reg: impl FnOnce(JobScheduler) -> F + 'a,
-) where F: Future<Output = Result<JobScheduler, JobSchedulerError>>
+) -> Result<(), JobSchedulerError>
+where F: Future<Output = Result<JobScheduler, JobSchedulerError>>
 {
-    if let Err(err) = async {
-        tracing::info!("starting chron job");
+    tracing::info!("starting chron job");
+    let res = async {
         let shed = JobScheduler::new().await?;
         let shed = reg(shed).await?;
-        shed.start().await?;
+        shed.start().await
+    }.await;
 
-        tracing::info!("started chron job");
-        Ok::<_, JobSchedulerError>(())
-    }.await {
-        tracing::error!("could not start chron job: {err}");
+    match &res {
+        Ok(()) => tracing::info!("started chron job"),
+        Err(err) => tracing::error!("could not start chron job: {err}"),
     }
+    res
 }
 
-pub async fn connect_db(cfg: &DbConfig) -> Option<mongodb::Database> {
-    match async {
-        mongodb::Client::with_options(
-            ClientOptions::parse(&cfg.url).await?,
-        )
-    }.await {
-        Ok(v) => {
-            Some(v.database(&cfg.database))
-        },
-        Err(err) => {
-            tracing::error!("could not connect to db: {err}");
-            None
-        },
-    }
+/// parses a comma separated list of serde identifiers (`vegan,fairtrade`)
+pub fn parse_list<'a, T>(list: &'a str) -> Result<HashSet<T>, serde::de::value::Error>
+where T: Deserialize<'a> + Eq + Hash
+{
+    list.split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(|v| {
+            let de: StrDeserializer<'a, serde::de::value::Error> = v.into_deserializer();
+            T::deserialize(de)
+        })
+    .collect()
 }
 
+/// builds a filter from the `with` (attributes) and `without` (allergens) params
+pub fn parse_filter(
+    with: Option<&str>, without: Option<&str>,
+) -> Result<MealFilter, serde::de::value::Error> {
+    Ok(MealFilter {
+        attributes: with.map(parse_list).transpose()?.unwrap_or_default(),
+        allergens: without.map(parse_list).transpose()?.unwrap_or_default(),
+    })
+}
 
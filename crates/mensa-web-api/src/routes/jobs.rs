@@ -0,0 +1,145 @@
+use std::{sync::Arc, time::Duration};
+
+use anyhow::Context;
+use chrono::Days;
+use futures::FutureExt;
+use tokio_cron_scheduler::Job;
+
+use crate::{
+    clock::Clock,
+    config::{Config, JobConfig, JobKind},
+    jobs::{JobRegistry, JobTask},
+};
+
+use super::{data::MealPlanManager, helpers::*};
+
+const DEFAULT_KEEP_DAYS: u32 = 90;
+
+/// how a job is triggered
+enum Trigger {
+    Cron(String),
+    Every(Duration),
+}
+
+/// registers the configured jobs, and schedules them if `schedule` is set
+pub async fn setup(
+    config: &Config, clock: &Clock, meals: &MealPlanManager,
+    registry: &JobRegistry, schedule: bool,
+) {
+    let mut triggers = Vec::new();
+
+    for (name, job) in config.job_names().into_iter().zip(&config.jobs) {
+        let tz = job.timezone.unwrap_or(clock.tz());
+        registry.register(&name, job.kind, &job.cron, tz, task(job, config, clock, meals));
+        triggers.push((name, tz, Trigger::Cron(job.cron.clone())));
+    }
+
+    // the interval from the cache config, unless there is a snapshot job
+    let has_snapshot_job = config.jobs.iter().any(|v| v.kind == JobKind::Snapshot);
+    if config.cache.snapshot.is_some() && !has_snapshot_job {
+        let job = JobConfig::new(JobKind::Snapshot, "");
+        let every = Duration::from_secs(config.cache.snapshot_interval * 60);
+        let schedule = format!("every {} minutes", config.cache.snapshot_interval);
+        registry.register("snapshot", JobKind::Snapshot, &schedule, clock.tz(), task(&job, config, clock, meals));
+        triggers.push(("snapshot".into(), clock.tz(), Trigger::Every(every)));
+    }
+
+    if !schedule { return }
+
+    let names: Vec<_> = triggers.iter().map(|(name, ..)| name.clone()).collect();
+    let reg = registry.clone();
+    let res = register_jobs(|shed| async move {
+        for (name, tz, trigger) in triggers {
+            let run = {
+                let (reg, name) = (reg.clone(), name.clone());
+                move |_, _| {
+                    let (reg, name) = (reg.clone(), name.clone());
+                    async move { reg.run(&name).await; }.boxed()
+                }
+            };
+            let job = match trigger {
+                Trigger::Cron(cron) => Job::new_async_tz(cron.as_str(), tz, run),
+                Trigger::Every(every) => Job::new_repeated_async(every, run),
+            };
+
+            let res = match job {
+                Ok(job) => shed.add(job).await.map(drop),
+                Err(err) => Err(err),
+            };
+            reg.scheduled(&name, res.map_err(|v| v.to_string()));
+        }
+        Ok(shed)
+    }).await;
+
+    if let Err(err) = res {
+        for name in names {
+            registry.scheduled(&name, Err(err.to_string()));
+        }
+    }
+}
+
+fn task(job: &JobConfig, config: &Config, clock: &Clock, meals: &MealPlanManager) -> JobTask {
+    let (meals, clock) = (meals.clone(), clock.clone());
+
+    match job.kind {
+        JobKind::Refresh => Arc::new(move || {
+            let meals = meals.clone();
+            async move {
+                let total = meals.tracked().len();
+                match meals.fetch_all().await {
+                    0 => Ok(format!("refreshed {total} plans")),
+                    failed => anyhow::bail!("could not fetch {failed} of {total} plans"),
+                }
+            }.boxed()
+        }),
+        JobKind::Retention => {
+            let keep_days = job.keep_days.unwrap_or(DEFAULT_KEEP_DAYS);
+            Arc::new(move || {
+                let (meals, clock) = (meals.clone(), clock.clone());
+                async move {
+                    let Some(persistence) = meals.persistence() else {
+                        return Ok("no db configured".into())
+                    };
+                    let before = clock.today().checked_sub_days(Days::new(keep_days.into()))
+                        .context("keep_days is out of range")?;
+                    let deleted = persistence.store().prune(&before).await?;
+                    Ok(format!("deleted {deleted} days before {before}"))
+                }.boxed()
+            })
+        },
+        JobKind::Cleanup => Arc::new(move || {
+            let (meals, clock) = (meals.clone(), clock.clone());
+            async move {
+                let dropped = meals.drop_past(clock.today()).await;
+                Ok(format!("dropped {dropped} past plans"))
+            }.boxed()
+        }),
+        JobKind::RetryWrites => Arc::new(move || {
+            let meals = meals.clone();
+            async move {
+                let Some(persistence) = meals.persistence() else {
+                    return Ok("no db configured".into())
+                };
+                if persistence.pending_days() == 0 {
+                    return Ok("nothing pending".into())
+                }
+                persistence.retry().await;
+                match persistence.pending_days() {
+                    0 => Ok("stored all pending days".into()),
+                    n => anyhow::bail!("{n} days are still pending"),
+                }
+            }.boxed()
+        }),
+        JobKind::Snapshot => {
+            let path = config.cache.snapshot.clone();
+            Arc::new(move || {
+                let (meals, path) = (meals.clone(), path.clone());
+                async move {
+                    let path = path.context("cache.snapshot is not set")?;
+                    meals.snapshot(&path).await?;
+                    Ok(format!("saved to {path:?}"))
+                }.boxed()
+            })
+        },
+    }
+}
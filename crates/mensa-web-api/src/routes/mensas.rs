@@ -0,0 +1,368 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use chrono::{DateTime, FixedOffset, NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use futures::future::join_all;
+use mensa_meal_api::{ClosureReason, DayState, MealPlan};
+
+use crate::{
+    clock::Clock,
+    config::{DirectoryConfig, HolidayConfig, OpeningHoursConfig},
+    directory::{Coordinates, MensaDirectory, MensaInfo},
+    holidays::HolidayCalendar,
+    opening_hours::{MensaStatus, OpenState, OpeningHours},
+};
+
+use super::{
+    data::{MealPlanError, MealPlanManager},
+    error::{ApiError, ErrorCode, Path, Query},
+    helpers::parse_filter,
+    negotiate,
+    v1::MealDay,
+};
+
+#[derive(Clone)]
+pub struct Directory {
+    mensas: Arc<MensaDirectory>,
+    strict: bool,
+}
+
+impl Directory {
+    pub async fn new(config: &DirectoryConfig, hours: &OpeningHours) -> Self {
+        let mensas = if let Some(path) = &config.path {
+            match MensaDirectory::load(path).await {
+                Ok(v) => {
+                    tracing::info!("loaded mensa directory from {path:?}");
+                    v
+                },
+                Err(err) => {
+                    tracing::error!("could not load mensa directory {path:?}: {err}, using bundled one");
+                    MensaDirectory::bundled()
+                },
+            }
+        } else { MensaDirectory::bundled() };
+        let mensas = mensas.with_hours(hours);
+
+        tracing::info!("using mensa directory v{}", mensas.version);
+        Self { mensas: Arc::new(mensas), strict: config.strict }
+    }
+
+    pub fn mensas(&self) -> &MensaDirectory { &self.mensas }
+
+    /// rejects malformed ids and ids that are not in the directory (if strict)
+    pub fn check(&self, id: &str) -> Result<(), ApiError> {
+        if !MensaDirectory::is_valid_id(id) {
+            return Err(ApiError::new(ErrorCode::InvalidMensaId)
+                .details(RejectedMensa { mensa: id.into(), suggestions: Vec::new() }))
+        }
+        if self.mensas.contains(id) { return Ok(()) }
+        if self.strict { return Err(self.unknown(id)) }
+
+        tracing::warn!("mensa {id} is not in the directory, asking upstream");
+        Ok(())
+    }
+
+    /// for ids upstream does not know either
+    pub fn unknown(&self, id: &str) -> ApiError {
+        ApiError::new(ErrorCode::UnknownMensa).details(RejectedMensa {
+            mensa: id.into(),
+            suggestions: self.mensas.suggest(id).into_iter().cloned().collect(),
+        })
+    }
+}
+
+/// opening hours, closures and holidays
+#[derive(Clone)]
+pub struct Calendar {
+    hours: Arc<OpeningHours>,
+    holidays: Arc<HolidayCalendar>,
+    closing_soon: chrono::Duration,
+}
+
+impl Calendar {
+    pub async fn new(config: &OpeningHoursConfig, holidays: &HolidayConfig) -> Self {
+        let mut hours = if let Some(path) = &config.path {
+            match OpeningHours::load(path).await {
+                Ok(v) => {
+                    tracing::info!("loaded opening hours from {path:?}");
+                    v
+                },
+                Err(err) => {
+                    tracing::error!("could not load opening hours {path:?}: {err}, using bundled ones");
+                    OpeningHours::bundled()
+                },
+            }
+        } else { OpeningHours::bundled() };
+        hours.add_exceptions(config.exceptions.iter().cloned());
+
+        Self {
+            hours: Arc::new(hours),
+            holidays: Arc::new(HolidayCalendar::new(holidays.builtin, holidays.extra.clone())),
+            closing_soon: chrono::Duration::minutes(config.closing_soon.into()),
+        }
+    }
+
+    pub fn hours(&self) -> &OpeningHours { &self.hours }
+
+    /// the state of `day`, with the plan from [`MealPlanManager::get_plan`]
+    pub async fn day_state(
+        &self, meals: &MealPlanManager,
+        mensa: &str, lang: Option<&str>, day: &NaiveDate,
+    ) -> Result<DayState, MealPlanError> {
+        let plan = meals.get_plan(mensa, lang).await.map(|v| v.data);
+        self.day_state_of(plan.as_ref(), mensa, day)
+    }
+
+    /// the state of `day`, upstream meals always win,
+    /// then closures, holidays, regular hours and gaps in the plan
+    pub fn day_state_of(
+        &self, plan: Result<&MealPlan, &MealPlanError>,
+        mensa: &str, day: &NaiveDate,
+    ) -> Result<DayState, MealPlanError> {
+        match plan {
+            Ok(plan) if plan.get_day_internal(day).is_some() => return Ok(DayState::Open),
+            Err(err @ MealPlanError::UnknownMensa) => return Err(err.clone()),
+            _ => {},
+        }
+
+        let closed = |reason| Ok(DayState::Closed { reason });
+        let hours = self.hours.day(mensa, *day);
+        if hours.hours.is_empty() {
+            if let Some(reason) = hours.reason {
+                return closed(ClosureReason::Closure(Some(reason.into())))
+            }
+        }
+        if let Some(name) = self.holidays.get(*day) {
+            return closed(ClosureReason::Holiday(name))
+        }
+        if hours.hours.is_empty() {
+            return closed(ClosureReason::RegularHours)
+        }
+
+        plan.map(|v| v.day_state(day)).map_err(Clone::clone)
+    }
+
+    /// the first opening after `at`, skips days that are closed
+    /// (see [`Self::day_state_of`]), looks two weeks ahead
+    pub fn next_opening(
+        &self, plan: Result<&MealPlan, &MealPlanError>,
+        mensa: &str, at: &DateTime<Tz>,
+    ) -> Option<DateTime<Tz>> {
+        const LOOKAHEAD: usize = 14;
+
+        let today = at.date_naive();
+        let (day, time) = today.iter_days().take(LOOKAHEAD)
+            .filter(|d| !matches!(self.day_state_of(plan, mensa, d), Ok(DayState::Closed { .. })))
+            .find_map(|d| {
+                let after = (d == today).then(|| at.time());
+                self.hours.opening_on(mensa, d, after).map(|v| (d, v))
+            })?;
+        at.timezone().from_local_datetime(&day.and_time(time)).earliest()
+    }
+}
+
+/// the details of `invalid_mensa_id` and `unknown_mensa`
+#[derive(Debug, serde::Serialize)]
+pub struct RejectedMensa {
+    mensa: String,
+    /// mensas that might have been meant
+    suggestions: Vec<MensaInfo>,
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MensaSearch {
+    /// matches name or campus
+    q: Option<String>,
+    campus: Option<String>,
+    /// `de` or `en`, defaults to `Accept-Language` and then `en`
+    lang: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct MensaList {
+    version: u32,
+    mensas: Vec<MensaInfo>,
+}
+
+/// the directory of mensas
+#[utoipa::path(
+    get, path = "/api/v1/mensas",
+    params(MensaSearch),
+    responses(
+        (status = 200, body = MensaList, headers(("Content-Language" = String), ("Vary" = String))),
+        (status = 400, body = ErrorBody, description = "invalid_query"),
+    ),
+)]
+pub async fn mensas(
+    Query(q): Query<MensaSearch>,
+    State(dir): State<Directory>,
+    headers: HeaderMap,
+) -> Response {
+    let lang = negotiate::lang(q.lang.as_deref(), &headers);
+    let mensas = dir.mensas();
+    let res = Json(MensaList {
+        version: mensas.version,
+        mensas: mensas.search(q.q.as_deref(), q.campus.as_deref())
+            .cloned()
+        .collect(),
+    });
+    negotiate::localized(res.into_response(), lang)
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NearbyQuery {
+    lat: f64,
+    lon: f64,
+    /// in meters
+    radius: Option<f64>,
+    /// with `menu`, defaults to and is capped at 10 (each menu might need an upstream fetch)
+    limit: Option<usize>,
+    /// include todays menu
+    #[serde(default)]
+    menu: bool,
+    /// `de` or `en`, defaults to `Accept-Language` and then `en`
+    lang: Option<String>,
+    /// comma separated attributes the meals need (`vegan`)
+    with: Option<String>,
+    /// comma separated allergens the meals may not contain (`gluten,nuts`)
+    without: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct NearbyMensa {
+    #[serde(flatten)]
+    mensa: MensaInfo,
+    /// in meters
+    distance: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    menu: Option<MealDay>,
+}
+
+/// how many mensas `nearby` returns with `menu`
+const MAX_MENUS: usize = 10;
+
+/// mensas sorted by distance, optionally with todays menu
+#[utoipa::path(
+    get, path = "/api/v1/mensas/nearby",
+    params(NearbyQuery),
+    responses(
+        (status = 200, body = [NearbyMensa], headers(("Content-Language" = String), ("Vary" = String))),
+        (status = 400, body = ErrorBody, description = "invalid_query, invalid_coordinates, invalid_filter"),
+    ),
+)]
+pub async fn nearby(
+    Query(q): Query<NearbyQuery>,
+    State(dir): State<Directory>,
+    State(meals): State<MealPlanManager>,
+    State(clock): State<Clock>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let lang = negotiate::lang(q.lang.as_deref(), &headers);
+    let pos = Coordinates { lat: q.lat, lon: q.lon };
+    if !pos.is_valid() {
+        return Err(ApiError::new(ErrorCode::InvalidCoordinates).lang(lang));
+    }
+    let filter = parse_filter(q.with.as_deref(), q.without.as_deref())
+        .map_err(|err| ApiError::new(ErrorCode::InvalidFilter)
+            .lang(lang)
+            .details(serde_json::json!({ "reason": err.to_string() }))
+        )?;
+
+    let mut mensas = dir.mensas().nearby(&pos, q.radius);
+    let limit = match q.limit {
+        Some(limit) if q.menu => Some(limit.min(MAX_MENUS)),
+        None if q.menu => Some(MAX_MENUS),
+        limit => limit,
+    };
+    if let Some(limit) = limit { mensas.truncate(limit) }
+
+    let today = clock.today();
+    let res = Json(join_all(mensas.into_iter().map(|(distance, mensa)| {
+        let (meals, filter) = (&meals, &filter);
+        async move {
+            let menu = if q.menu {
+                meals.get_day(&mensa.id, lang, &today).await.ok().flatten()
+                    .map(|v| (&v.data.filter(filter)).into())
+            } else { None };
+
+            NearbyMensa { mensa: mensa.clone(), distance, menu }
+        }
+    })).await);
+    Ok(negotiate::localized(res.into_response(), lang))
+}
+
+#[derive(Debug, serde::Deserialize, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatusQuery {
+    /// rfc3339, defaults to now
+    #[param(value_type = Option<String>, format = DateTime)]
+    at: Option<DateTime<FixedOffset>>,
+    /// `de` or `en`, defaults to `Accept-Language` and then `en`
+    lang: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct StatusResponse {
+    mensa: String,
+    #[schema(value_type = String, format = DateTime)]
+    at: DateTime<Tz>,
+    #[serde(flatten)]
+    #[schema(inline)]
+    status: MensaStatus<Tz>,
+}
+
+/// whether a mensa is open right now (or `at`)
+#[utoipa::path(
+    get, path = "/api/v1/mensas/{id}/status",
+    params(("id" = String, Path, description = "the upstream id"), StatusQuery),
+    responses(
+        (status = 200, body = StatusResponse, headers(("Content-Language" = String), ("Vary" = String))),
+        (status = 400, body = ErrorBody, description = "invalid_query, invalid_path, invalid_mensa_id"),
+        (status = 404, body = ErrorBody, description = "unknown_mensa"),
+    ),
+)]
+pub async fn status(
+    Path(id): Path<String>,
+    Query(q): Query<StatusQuery>,
+    State(dir): State<Directory>,
+    State(calendar): State<Calendar>,
+    State(meals): State<MealPlanManager>,
+    State(clock): State<Clock>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let lang = negotiate::lang(q.lang.as_deref(), &headers);
+    dir.check(&id).map_err(|err| err.lang(lang))?;
+
+    let at = q.at.map_or_else(|| clock.now(), |v| v.with_timezone(&clock.tz()));
+    let mut status = calendar.hours().status(&id, &at, calendar.closing_soon);
+    let plan = meals.get_plan(&id, lang).await.map(|v| v.data);
+
+    // the hours say open, but it might be a holiday or there is no food
+    if status.status != OpenState::Closed {
+        if let Ok(DayState::Closed { reason }) = calendar.day_state_of(plan.as_ref(), &id, &at.date_naive()) {
+            status = MensaStatus {
+                status: OpenState::Closed,
+                reason: Some(reason.as_str().into()),
+                detail: reason.detail().map(Into::into),
+                closes_at: None,
+                opens_at: None,
+            };
+        }
+    }
+    // the hours do not know holidays or days without food
+    if status.status == OpenState::Closed {
+        status.opens_at = calendar.next_opening(plan.as_ref(), &id, &at);
+    }
+
+    let res = Json(StatusResponse { mensa: id, at, status });
+    Ok(negotiate::localized(res.into_response(), lang))
+}
@@ -0,0 +1,71 @@
+use axum::{
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+};
+use mensa_meal_api::{Format, MealPlans};
+
+/// `?format=` wins over `Accept`, anything unknown gets json
+pub fn format(query: Option<Format>, headers: &HeaderMap) -> Format {
+    if let Some(format) = query { return format }
+
+    let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+        return Format::Json
+    };
+    preferences(accept)
+        .find_map(|mime| match mime {
+            "*/*" | "application/*" => Some(Format::Json),
+            "text/*" => Some(Format::Text),
+            mime => Format::from_mime(mime),
+        })
+    .unwrap_or(Format::Json)
+}
+
+/// `?lang=` wins over `Accept-Language`, which only picks languages upstream has,
+/// `None` is [`MealPlans::DEFAULT_LANG`]
+pub fn lang<'a>(query: Option<&'a str>, headers: &HeaderMap) -> Option<&'a str> {
+    if query.is_some() { return query }
+
+    let accept = headers.get(header::ACCEPT_LANGUAGE)?.to_str().ok()?;
+    preferences(accept).find_map(|tag| {
+        if tag == "*" { return Some(MealPlans::DEFAULT_LANG) }
+        // `de-DE` is german too
+        let primary = tag.split('-').next()?;
+        MealPlans::LANGS.iter().copied().find(|v| v.eq_ignore_ascii_case(primary))
+    })
+}
+
+/// sets `Content-Language` and adds `Accept-Language` to `Vary`
+pub fn localized(mut res: Response, lang: Option<&str>) -> Response {
+    let lang = lang.unwrap_or(MealPlans::DEFAULT_LANG);
+    let headers = res.headers_mut();
+    // whatever upstream answers unknown `?lang=`s with, it is not known here
+    if MealPlans::LANGS.contains(&lang) {
+        headers.insert(header::CONTENT_LANGUAGE, HeaderValue::from_str(lang).expect("valid header"));
+    }
+
+    let vary = match headers.get(header::VARY).and_then(|v| v.to_str().ok()) {
+        Some(v) => format!("{v}, accept-language"),
+        None => "accept-language".into(),
+    };
+    if let Ok(v) = HeaderValue::from_str(&vary) {
+        headers.insert(header::VARY, v);
+    }
+    res
+}
+
+/// the values of an `Accept*` header by quality, without the rejected ones (`q=0`)
+fn preferences(header: &str) -> impl Iterator<Item = &str> {
+    let mut values: Vec<(&str, f32)> = header.split(',')
+        .filter_map(|v| {
+            let mut params = v.split(';').map(str::trim);
+            let value = params.next().filter(|v| !v.is_empty())?;
+            let q = params
+                .find_map(|p| p.strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+            (q > 0.0).then_some((value, q))
+        })
+    .collect();
+    // stable, so equal qualities keep the client's order
+    values.sort_by(|a, b| b.1.total_cmp(&a.1));
+    values.into_iter().map(|(v, _)| v)
+}
@@ -0,0 +1,77 @@
+use axum::{response::Html, Json};
+use mensa_meal_api::{Format, PriceGroup};
+use utoipa::{
+    openapi::{
+        KnownFormat, ObjectBuilder, OneOfBuilder, RefOr, Schema, SchemaFormat, SchemaType,
+    },
+    OpenApi, ToSchema,
+};
+
+use crate::{
+    directory::{Coordinates, MensaInfo},
+    opening_hours::OpenState,
+};
+
+use super::{
+    error::{ErrorBody, ErrorCode, ErrorContent},
+    freshness::{DataSource, FreshMealDay, FreshMealPlan, Freshness},
+    mensas::{MensaList, NearbyMensa, StatusResponse},
+    v1, MensaDate,
+};
+
+/// the public endpoints, `/api/admin` is left out
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "mensa-web-api",
+        description = "meal plans of the Studierendenwerk Berlin\n\n\
+            the routes without `/v1` are deprecated aliases of the `/api/v1` ones",
+    ),
+    paths(
+        super::meals,
+        super::meals_plan,
+        super::mensas::mensas,
+        super::mensas::nearby,
+        super::mensas::status,
+    ),
+    components(schemas(
+        MensaDate, Format, PriceGroup,
+        FreshMealDay, FreshMealPlan, Freshness, DataSource,
+        v1::MealDay, v1::MealPlan, v1::Meal, v1::MealInfo, v1::MealPrice, v1::EnvRating,
+        v1::DayState, v1::ClosureReason,
+        v1::Rating, v1::MealAttribute, v1::MealAllergen, v1::MealAddative,
+        MensaList, MensaInfo, Coordinates, NearbyMensa, StatusResponse, OpenState,
+        ErrorBody, ErrorContent, ErrorCode,
+    )),
+)]
+pub struct ApiDoc;
+
+/// untagged, so it is spelled out by hand
+impl<'s> ToSchema<'s> for MensaDate {
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let strings = |values: &[&str]| ObjectBuilder::new()
+            .schema_type(SchemaType::String)
+            .enum_values(Some(values.iter().copied()))
+        .build();
+
+        ("MensaDate", OneOfBuilder::new()
+            .description(Some("a day relative to today, a weekday of the current week or a date"))
+            .item(strings(&["today", "yesterday", "tomorrow"]))
+            .item(strings(&["mon", "tue", "wed", "thu", "fri", "sat", "sun"]))
+            .item(ObjectBuilder::new()
+                .schema_type(SchemaType::String)
+                .format(Some(SchemaFormat::KnownFormat(KnownFormat::Date)))
+            .build())
+        .into())
+    }
+}
+
+pub async fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// a page that renders the spec and can send the get requests,
+/// self contained so nothing is loaded from elsewhere
+pub async fn docs() -> Html<&'static str> {
+    Html(include_str!("../../data/docs.html"))
+}
@@ -0,0 +1,326 @@
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::NaiveDate;
+use mensa_meal_api as meal_api;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::freshness::ContentHash;
+
+// the response types of `/api/v1`, separate from `mensa_meal_api`'s model
+// so refactoring it does not change what clients get, only change these
+// in a backwards compatible way (breaking changes go to a `v2`)
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MealPlan {
+    pub mensa_name: String,
+    /// sorted by date
+    pub days: Vec<MealDay>,
+    #[serde(skip)]
+    content_hash: u64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MealDay {
+    pub date: NaiveDate,
+    pub state: DayState,
+    /// empty unless the mensa is open
+    pub categories: HashMap<String, Vec<Meal>>,
+    #[serde(skip)]
+    content_hash: u64,
+}
+
+/// why a day does (not) have meals
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DayState {
+    Open,
+    Closed { reason: ClosureReason },
+    /// after the last day upstream has published
+    NotYetPublished,
+    /// before the published range or no plan at all
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ClosureReason {
+    /// with the name of the holiday
+    Holiday(String),
+    /// semester break, renovation, ...
+    Closure(Option<String>),
+    /// weekends
+    RegularHours,
+    /// upstream has no meals for a day within the published range
+    NoMeals,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Meal {
+    pub title: String,
+    pub description: Option<String>,
+    pub price: Option<MealPrice>,
+    pub info: MealInfo,
+    /// upstream's article id
+    pub id: String,
+}
+
+/// in euro with a comma, like `3,45`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MealPrice {
+    pub students: String,
+    pub servants: String,
+    pub guests: String,
+}
+
+// sorted sets, so the same meal always serializes the same way
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MealInfo {
+    pub env_rating: EnvRating,
+    pub addatives: BTreeSet<MealAddative>,
+    pub allergens: BTreeSet<MealAllergen>,
+    pub attributes: BTreeSet<MealAttribute>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct EnvRating {
+    pub health: Option<Rating>,
+    pub co2: Option<Rating>,
+    pub h2o: Option<Rating>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MealAttribute {
+    Vegan,
+    Fairtrade,
+    ClimateFood,
+    Vegetarian,
+    SustainableFarming,
+    SustainableFishing,
+    Frozen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MealAllergen {
+    Gluten,       // 21
+    Wheat,        // 21a
+    Rye,          // 21b
+    Barley,       // 21c
+    Oats,         // 21d
+    Spelt,        // 21e
+    Hand,         // 21f
+    Crustaceans,  // 22
+    Eggs,         // 23
+    Fish,         // 24
+    Peanuts,      // 25
+    Nuts,         // 26
+    Almonds,      // 26a
+    Hazelnut,     // 26b
+    Wallnut,      // 26c
+    Cashew,       // 26d
+    Pecan,        // 26e
+    Paranus,      // 26f
+    Pistacio,     // 26g
+    Macadamia,    // 26h
+    Cellery,      // 27
+    Soy,          // 28
+    Mustard,      // 29
+    MilkProducts, // 30
+    Sesame,       // 31
+    Sulfides,     // 32
+    Lupine,       // 33
+    Molluscs,     // 34
+    NitriteSalt,  // 35
+    Yeast,        // 36
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MealAddative {
+    Pork,               // 2
+    Alcohol,            // 3
+    FlavourEnhancer,    // 4
+    Waxed,              // 5
+    Preserved,          // 6
+    Antioxidants,       // 7
+    Coloring,           // 8
+    Phosphate,          // 9
+    Darkened,           // 10
+    Phenylalaninsource, // 12
+    Sweeteners,         // 13
+    SmallFishParts,     // 14
+    Caffeine,           // 16
+    Chitin,             // 17
+    Sulfur,             // 19
+    LaxativeEffect,     // 20
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Rating { Red, Yellow, Green }
+
+impl From<&meal_api::MealPlan> for MealPlan {
+    fn from(v: &meal_api::MealPlan) -> Self {
+        Self {
+            mensa_name: v.mensa().into(),
+            days: v.days().map(Into::into).collect(),
+            content_hash: v.content_hash(),
+        }
+    }
+}
+
+impl From<&meal_api::MealDay> for MealDay {
+    fn from(v: &meal_api::MealDay) -> Self {
+        Self {
+            date: v.date,
+            state: (&v.state).into(),
+            categories: v.categories.iter()
+                .map(|(k, v)| (k.clone(), v.iter().map(Into::into).collect()))
+            .collect(),
+            content_hash: v.content_hash(),
+        }
+    }
+}
+
+impl From<&meal_api::DayState> for DayState {
+    fn from(v: &meal_api::DayState) -> Self {
+        match v {
+            meal_api::DayState::Open => Self::Open,
+            meal_api::DayState::Closed { reason } => Self::Closed { reason: reason.into() },
+            meal_api::DayState::NotYetPublished => Self::NotYetPublished,
+            meal_api::DayState::Unknown => Self::Unknown,
+        }
+    }
+}
+
+impl From<&meal_api::ClosureReason> for ClosureReason {
+    fn from(v: &meal_api::ClosureReason) -> Self {
+        match v {
+            meal_api::ClosureReason::Holiday(name) => Self::Holiday(name.clone()),
+            meal_api::ClosureReason::Closure(reason) => Self::Closure(reason.clone()),
+            meal_api::ClosureReason::RegularHours => Self::RegularHours,
+            meal_api::ClosureReason::NoMeals => Self::NoMeals,
+        }
+    }
+}
+
+impl From<&meal_api::MensaMeal> for Meal {
+    fn from(v: &meal_api::MensaMeal) -> Self {
+        let info = v.info();
+        let rating = info.env_rating();
+        Self {
+            title: v.title().into(),
+            description: v.description().map(Into::into),
+            price: v.price().map(|v| MealPrice {
+                students: v.students().to_string(),
+                servants: v.servants().to_string(),
+                guests: v.guests().to_string(),
+            }),
+            info: MealInfo {
+                env_rating: EnvRating {
+                    health: rating.health().map(Into::into),
+                    co2: rating.co2().map(Into::into),
+                    h2o: rating.h2o().map(Into::into),
+                },
+                addatives: info.addatives().iter().copied().map(Into::into).collect(),
+                allergens: info.allergens().iter().copied().map(Into::into).collect(),
+                attributes: info.attributes().iter().copied().map(Into::into).collect(),
+            },
+            id: v.id().into(),
+        }
+    }
+}
+
+impl From<meal_api::MealAttribute> for MealAttribute {
+    fn from(v: meal_api::MealAttribute) -> Self {
+        match v {
+            meal_api::MealAttribute::Vegan => Self::Vegan,
+            meal_api::MealAttribute::Fairtrade => Self::Fairtrade,
+            meal_api::MealAttribute::ClimateFood => Self::ClimateFood,
+            meal_api::MealAttribute::Vegetarian => Self::Vegetarian,
+            meal_api::MealAttribute::SustainableFarming => Self::SustainableFarming,
+            meal_api::MealAttribute::SustainableFishing => Self::SustainableFishing,
+            meal_api::MealAttribute::Frozen => Self::Frozen,
+        }
+    }
+}
+
+impl From<meal_api::MealAllergen> for MealAllergen {
+    fn from(v: meal_api::MealAllergen) -> Self {
+        match v {
+            meal_api::MealAllergen::Gluten => Self::Gluten,
+            meal_api::MealAllergen::Wheat => Self::Wheat,
+            meal_api::MealAllergen::Rye => Self::Rye,
+            meal_api::MealAllergen::Barley => Self::Barley,
+            meal_api::MealAllergen::Oats => Self::Oats,
+            meal_api::MealAllergen::Spelt => Self::Spelt,
+            meal_api::MealAllergen::Hand => Self::Hand,
+            meal_api::MealAllergen::Crustaceans => Self::Crustaceans,
+            meal_api::MealAllergen::Eggs => Self::Eggs,
+            meal_api::MealAllergen::Fish => Self::Fish,
+            meal_api::MealAllergen::Peanuts => Self::Peanuts,
+            meal_api::MealAllergen::Nuts => Self::Nuts,
+            meal_api::MealAllergen::Almonds => Self::Almonds,
+            meal_api::MealAllergen::Hazelnut => Self::Hazelnut,
+            meal_api::MealAllergen::Wallnut => Self::Wallnut,
+            meal_api::MealAllergen::Cashew => Self::Cashew,
+            meal_api::MealAllergen::Pecan => Self::Pecan,
+            meal_api::MealAllergen::Paranus => Self::Paranus,
+            meal_api::MealAllergen::Pistacio => Self::Pistacio,
+            meal_api::MealAllergen::Macadamia => Self::Macadamia,
+            meal_api::MealAllergen::Cellery => Self::Cellery,
+            meal_api::MealAllergen::Soy => Self::Soy,
+            meal_api::MealAllergen::Mustard => Self::Mustard,
+            meal_api::MealAllergen::MilkProducts => Self::MilkProducts,
+            meal_api::MealAllergen::Sesame => Self::Sesame,
+            meal_api::MealAllergen::Sulfides => Self::Sulfides,
+            meal_api::MealAllergen::Lupine => Self::Lupine,
+            meal_api::MealAllergen::Molluscs => Self::Molluscs,
+            meal_api::MealAllergen::NitriteSalt => Self::NitriteSalt,
+            meal_api::MealAllergen::Yeast => Self::Yeast,
+        }
+    }
+}
+
+impl From<meal_api::MealAddative> for MealAddative {
+    fn from(v: meal_api::MealAddative) -> Self {
+        match v {
+            meal_api::MealAddative::Pork => Self::Pork,
+            meal_api::MealAddative::Alcohol => Self::Alcohol,
+            meal_api::MealAddative::FlavourEnhancer => Self::FlavourEnhancer,
+            meal_api::MealAddative::Waxed => Self::Waxed,
+            meal_api::MealAddative::Preserved => Self::Preserved,
+            meal_api::MealAddative::Antioxidants => Self::Antioxidants,
+            meal_api::MealAddative::Coloring => Self::Coloring,
+            meal_api::MealAddative::Phosphate => Self::Phosphate,
+            meal_api::MealAddative::Darkened => Self::Darkened,
+            meal_api::MealAddative::Phenylalaninsource => Self::Phenylalaninsource,
+            meal_api::MealAddative::Sweeteners => Self::Sweeteners,
+            meal_api::MealAddative::SmallFishParts => Self::SmallFishParts,
+            meal_api::MealAddative::Caffeine => Self::Caffeine,
+            meal_api::MealAddative::Chitin => Self::Chitin,
+            meal_api::MealAddative::Sulfur => Self::Sulfur,
+            meal_api::MealAddative::LaxativeEffect => Self::LaxativeEffect,
+        }
+    }
+}
+
+impl From<meal_api::Rating> for Rating {
+    fn from(v: meal_api::Rating) -> Self {
+        match v {
+            meal_api::Rating::Red => Self::Red,
+            meal_api::Rating::Yellow => Self::Yellow,
+            meal_api::Rating::Green => Self::Green,
+        }
+    }
+}
+
+impl ContentHash for MealDay {
+    fn content_hash(&self) -> u64 { self.content_hash }
+}
+
+impl ContentHash for MealPlan {
+    fn content_hash(&self) -> u64 { self.content_hash }
+}
@@ -0,0 +1,121 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use mensa_meal_api::{MealDay, MealPlan};
+
+use crate::config::{DbConfig, DbKind};
+
+mod memory;
+mod mongo;
+mod persistence;
+mod sqlite;
+pub use memory::MemoryStore;
+pub use mongo::MealCollections;
+pub use persistence::{Persistence, PersistenceState, PersistenceStatus};
+pub use sqlite::SqliteStore;
+
+/// persistence for fetched plans, used when a day is not in memory
+///
+/// `lang: None` means the default language (see [`MealPlans::DEFAULT_LANG`])
+///
+/// [`MealPlans::DEFAULT_LANG`]: mensa_meal_api::MealPlans::DEFAULT_LANG
+#[async_trait::async_trait]
+pub trait MealStore: Send + Sync {
+    /// inserts or replaces all days of `plan` (with its fetch time) in a transaction
+    /// where the backend has them, see [`StoreError::Partial`] for the others
+    async fn store_plan(
+        &self, mensa_id: &str, lang: Option<&str>, plan: &MealPlan,
+    ) -> Result<(), StoreError>;
+
+    async fn get_day(
+        &self, mensa_id: &str, lang: Option<&str>, day: &NaiveDate,
+    ) -> Result<Option<StoredDay>, StoreError>;
+
+    /// days within `from..=to`, oldest first
+    async fn get_range(
+        &self, mensa_id: &str, lang: Option<&str>,
+        from: &NaiveDate, to: &NaiveDate,
+    ) -> Result<Vec<StoredDay>, StoreError>;
+
+    /// up to `limit` days before `before`, newest first
+    async fn history(
+        &self, mensa_id: &str, lang: Option<&str>,
+        before: &NaiveDate, limit: usize,
+    ) -> Result<Vec<StoredDay>, StoreError>;
+
+    /// every (mensa, lang) that has a stored plan
+    async fn list_mensas(&self) -> Result<Vec<StoredMensa>, StoreError>;
+
+    /// deletes all days before `before` (of every mensa), returns how many
+    async fn prune(&self, before: &NaiveDate) -> Result<usize, StoreError>;
+}
+
+pub type Store = Arc<dyn MealStore>;
+
+#[derive(Debug, Clone)]
+pub struct StoredDay {
+    pub meal: MealDay,
+    /// when the plan with this day was fetched,
+    /// `None` for days stored before that was kept
+    pub fetched_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct StoredMensa {
+    pub mensa_id: String,
+    pub lang: String,
+    pub name: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StoreError {
+    #[error(transparent)]
+    Mongo(#[from] mongodb::error::Error),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("invalid stored data: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    Bson(#[from] mongodb::bson::ser::Error),
+    #[error(transparent)]
+    Task(#[from] tokio::task::JoinError),
+    /// the other days were written, unless the write was a transaction
+    #[error("{} of {total} days could not be written", failed.len())]
+    Partial { failed: Vec<NaiveDate>, total: usize },
+}
+
+/// opens the configured backend, `None` (and an error log) if that fails
+pub async fn connect(config: &DbConfig) -> Option<Store> {
+    match config.kind {
+        DbKind::Mongo => {
+            tracing::info!("connecting to mongodb");
+            let (client, db) = mongo::connect_db(config).await?;
+            tracing::info!("connected to mongodb");
+
+            let collections = MealCollections::new(&client, &db);
+            // the unique indexes are what keeps the writes from duplicating days
+            match collections.init().await {
+                Ok(()) => tracing::info!("db schema and indexes are up to date"),
+                Err(err) => {
+                    tracing::error!("could not migrate db: {err}");
+                    return None
+                },
+            }
+            Some(Arc::new(collections))
+        },
+        DbKind::Sqlite => match SqliteStore::open(&config.path) {
+            Ok(v) => {
+                tracing::info!("opened sqlite db {:?}", config.path);
+                Some(Arc::new(v))
+            },
+            Err(err) => {
+                tracing::error!("could not open sqlite db {:?}: {err}", config.path);
+                None
+            },
+        },
+        DbKind::Memory => {
+            tracing::info!("using in-memory store");
+            Some(Arc::new(MemoryStore::default()))
+        },
+    }
+}
@@ -0,0 +1,93 @@
+use std::{collections::BTreeMap, sync::RwLock};
+
+use chrono::NaiveDate;
+use mensa_meal_api::{MealPlan, MealPlans};
+
+use super::{MealStore, StoreError, StoredDay, StoredMensa};
+
+/// keeps everything in memory, for tests and small deployments
+/// that do not care about restarts
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    mensas: RwLock<BTreeMap<(String, String), StoredPlan>>,
+}
+
+#[derive(Debug)]
+struct StoredPlan {
+    name: String,
+    days: BTreeMap<NaiveDate, StoredDay>,
+}
+
+impl MemoryStore {
+    fn key(mensa_id: &str, lang: Option<&str>) -> (String, String) {
+        (mensa_id.into(), lang.unwrap_or(MealPlans::DEFAULT_LANG).into())
+    }
+
+    fn days<T>(
+        &self, mensa_id: &str, lang: Option<&str>,
+        f: impl FnOnce(&BTreeMap<NaiveDate, StoredDay>) -> T,
+    ) -> Option<T> {
+        self.mensas.read().unwrap()
+            .get(&Self::key(mensa_id, lang))
+        .map(|v| f(&v.days))
+    }
+}
+
+#[async_trait::async_trait]
+impl MealStore for MemoryStore {
+    async fn store_plan(
+        &self, mensa_id: &str, lang: Option<&str>, plan: &MealPlan,
+    ) -> Result<(), StoreError> {
+        let mut mensas = self.mensas.write().unwrap();
+        let stored = mensas.entry(Self::key(mensa_id, lang))
+            .or_insert_with(|| StoredPlan { name: String::new(), days: BTreeMap::new() });
+
+        stored.name = plan.mensa().into();
+        stored.days.extend(plan.days().map(|v| (v.date, StoredDay {
+            meal: v.clone(),
+            fetched_at: plan.fetched_at(),
+        })));
+        Ok(())
+    }
+
+    async fn get_day(
+        &self, mensa_id: &str, lang: Option<&str>, day: &NaiveDate,
+    ) -> Result<Option<StoredDay>, StoreError> {
+        Ok(self.days(mensa_id, lang, |v| v.get(day).cloned()).flatten())
+    }
+
+    async fn get_range(
+        &self, mensa_id: &str, lang: Option<&str>,
+        from: &NaiveDate, to: &NaiveDate,
+    ) -> Result<Vec<StoredDay>, StoreError> {
+        if from > to { return Ok(Vec::new()) }
+
+        Ok(self.days(mensa_id, lang, |v| {
+            v.range(from..=to).map(|(_, v)| v.clone()).collect()
+        }).unwrap_or_default())
+    }
+
+    async fn history(
+        &self, mensa_id: &str, lang: Option<&str>,
+        before: &NaiveDate, limit: usize,
+    ) -> Result<Vec<StoredDay>, StoreError> {
+        Ok(self.days(mensa_id, lang, |v| {
+            v.range(..before).rev().take(limit).map(|(_, v)| v.clone()).collect()
+        }).unwrap_or_default())
+    }
+
+    async fn list_mensas(&self) -> Result<Vec<StoredMensa>, StoreError> {
+        Ok(self.mensas.read().unwrap().iter().map(|((mensa_id, lang), v)| StoredMensa {
+            mensa_id: mensa_id.clone(),
+            lang: lang.clone(),
+            name: v.name.clone(),
+        }).collect())
+    }
+
+    async fn prune(&self, before: &NaiveDate) -> Result<usize, StoreError> {
+        Ok(self.mensas.write().unwrap().values_mut().map(|v| {
+            let kept = v.days.split_off(before);
+            std::mem::replace(&mut v.days, kept).len()
+        }).sum())
+    }
+}
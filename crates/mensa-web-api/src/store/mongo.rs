@@ -0,0 +1,414 @@
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::TryStreamExt;
+use mensa_meal_api::{MealDay, MealPlan, MealPlans};
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Document},
+    options::{
+        AggregateOptions, ClientOptions, FindOneAndReplaceOptions, FindOptions,
+        IndexOptions, ReturnDocument, UpdateOptions,
+    },
+    error::ErrorKind,
+    Client, ClientSession, Collection, Database, IndexModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::DbConfig;
+
+use super::{MealStore, StoreError, StoredDay, StoredMensa};
+
+/// bumped whenever the stored documents change shape,
+/// add a matching step to [`MealCollections::migrate`]
+const SCHEMA_VERSION: u32 = 1;
+
+const MENSA_INDEX: &str = "mensa_id_lang";
+const MEAL_INDEX: &str = "mensa_record_id_date";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MensaMealDay {
+    pub mensa_record_id: ObjectId,
+    pub meal: MealDay,
+    /// missing on documents from before it was kept
+    #[serde(default)]
+    pub fetched_at: Option<DateTime<Utc>>,
+    /// missing on documents from before versioning (0)
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MensaData {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub _id: Option<ObjectId>,
+    pub name: String,
+    pub mensa_id: String,
+    pub lang: String,
+    #[serde(default)]
+    pub schema_version: u32,
+}
+
+/// the single document in `meta` that tracks the applied migrations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SchemaInfo {
+    _id: String,
+    version: u32,
+}
+
+/// the client, which is needed for sessions, and the configured database
+pub async fn connect_db(cfg: &DbConfig) -> Option<(Client, Database)> {
+    match async {
+        Client::with_options(
+            ClientOptions::parse(&cfg.url).await?,
+        )
+    }.await {
+        Ok(v) => {
+            let db = v.database(&cfg.database);
+            Some((v, db))
+        },
+        Err(err) => {
+            tracing::error!("could not connect to db: {err}");
+            None
+        },
+    }
+}
+
+#[derive(Clone)]
+pub struct MealCollections {
+    client: Client,
+    db: Database,
+    meals: Collection<MensaMealDay>,
+    mensas: Collection<MensaData>,
+    meta: Collection<SchemaInfo>,
+    /// cleared once the server turns out to not support them
+    transactions: Arc<AtomicBool>,
+}
+
+impl MealCollections {
+    pub fn new(client: &Client, db: &Database) -> Self {
+        Self {
+            client: client.clone(),
+            db: db.clone(),
+            meals: db.collection("meals"),
+            mensas: db.collection("mensas"),
+            meta: db.collection("meta"),
+            transactions: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    /// migrates old documents and creates the indexes, run at startup
+    pub async fn init(&self) -> Result<(), StoreError> {
+        self.migrate().await?;
+        self.dedup().await?;
+        self.create_indexes().await
+    }
+
+    async fn migrate(&self) -> Result<(), StoreError> {
+        const SCHEMA_ID: &str = "schema";
+
+        let current = self.meta.find_one(doc! { "_id": SCHEMA_ID }, None).await?
+            .map_or(0, |v| v.version);
+        if current > SCHEMA_VERSION {
+            tracing::warn!("db schema v{current} is newer than v{SCHEMA_VERSION}, skipping migrations");
+            return Ok(())
+        }
+
+        for version in current + 1..=SCHEMA_VERSION {
+            tracing::info!("migrating db to schema v{version}");
+            match version {
+                // documents from before day states and versioning
+                1 => {
+                    self.meals.update_many(
+                        doc! { "meal.state": { "$exists": false } },
+                        doc! { "$set": { "meal.state": { "kind": "open" } } },
+                        None,
+                    ).await?;
+                },
+                _ => unreachable!("missing migration to schema v{version}"),
+            }
+
+            // `$not` also matches documents without the field
+            let outdated = doc! { "schema_version": { "$not": { "$gte": version } } };
+            let set = doc! { "$set": { "schema_version": version } };
+            self.meals.update_many(outdated.clone(), set.clone(), None).await?;
+            self.mensas.update_many(outdated, set, None).await?;
+
+            self.meta.update_one(
+                doc! { "_id": SCHEMA_ID },
+                doc! { "$set": { "version": version } },
+                UpdateOptions::builder().upsert(true).build(),
+            ).await?;
+        }
+
+        Ok(())
+    }
+
+    /// removes the duplicates the unique indexes would fail on,
+    /// only needed until they exist
+    async fn dedup(&self) -> Result<(), StoreError> {
+        if has_index(&self.mensas, MENSA_INDEX).await? && has_index(&self.meals, MEAL_INDEX).await? {
+            return Ok(())
+        }
+
+        let mensas = self.db.collection::<Document>(self.mensas.name());
+        let meals = self.db.collection::<Document>(self.meals.name());
+
+        // the ids of every group with more than one document, the most recently created last
+        let duplicates = |keys: Document| vec![
+            doc! { "$sort": { "_id": 1 } },
+            doc! { "$group": { "_id": keys, "ids": { "$push": "$_id" } } },
+            doc! { "$match": { "ids.1": { "$exists": true } } },
+        ];
+        let options = || AggregateOptions::builder().allow_disk_use(true).build();
+
+        // the meals of the dropped mensa documents move to the kept one,
+        // which may duplicate days, so this goes first
+        let groups: Vec<Document> = mensas.aggregate(
+            duplicates(doc! { "mensa_id": "$mensa_id", "lang": "$lang" }), options(),
+        ).await?.try_collect().await?;
+        for group in groups {
+            let mut ids = group.get_array("ids").cloned().unwrap_or_default();
+            let Some(keep) = ids.pop() else { continue };
+            tracing::warn!("merging {} duplicate mensa documents", ids.len());
+            meals.update_many(
+                doc! { "mensa_record_id": { "$in": ids.clone() } },
+                doc! { "$set": { "mensa_record_id": keep } },
+                None,
+            ).await?;
+            mensas.delete_many(doc! { "_id": { "$in": ids } }, None).await?;
+        }
+
+        let groups: Vec<Document> = meals.aggregate(
+            duplicates(doc! { "mensa_record_id": "$mensa_record_id", "date": "$meal.date" }), options(),
+        ).await?.try_collect().await?;
+        let mut removed = 0;
+        for group in groups {
+            let mut ids = group.get_array("ids").cloned().unwrap_or_default();
+            ids.pop();
+            removed += meals.delete_many(doc! { "_id": { "$in": ids } }, None).await?.deleted_count;
+        }
+        if removed > 0 {
+            tracing::warn!("removed {removed} duplicate days");
+        }
+
+        Ok(())
+    }
+
+    async fn create_indexes(&self) -> Result<(), StoreError> {
+        let unique = |name: &str| IndexOptions::builder()
+            .unique(true)
+            .name(name.to_string())
+        .build();
+
+        self.mensas.create_index(IndexModel::builder()
+            .keys(doc! { "mensa_id": 1, "lang": 1 })
+            .options(unique(MENSA_INDEX))
+        .build(), None).await?;
+
+        self.meals.create_index(IndexModel::builder()
+            .keys(doc! { "mensa_record_id": 1, "meal.date": 1 })
+            .options(unique(MEAL_INDEX))
+        .build(), None).await?;
+
+        Ok(())
+    }
+
+    async fn mensa_record_id(
+        &self, mensa_id: &str, lang: Option<&str>,
+    ) -> Result<Option<ObjectId>, StoreError> {
+        Ok(self.mensas.find_one(doc! {
+            "mensa_id": mensa_id,
+            "lang": lang.unwrap_or(MealPlans::DEFAULT_LANG),
+        }, None).await?.and_then(|v| v._id))
+    }
+
+    /// upserts the mensa and then its days, both in `session`
+    async fn write_plan(
+        &self, session: &mut ClientSession,
+        mensa_id: &str, lang: Option<&str>,
+        plan: &MealPlan,
+    ) -> Result<(), StoreError> {
+        let mensa_id = mensa_id.to_string();
+        let lang = lang.unwrap_or(MealPlans::DEFAULT_LANG).to_string();
+        let mensa = self.mensas.find_one_and_replace_with_session(doc! {
+            "mensa_id": &mensa_id,
+            "lang": &lang,
+        }, MensaData {
+            _id: None,
+            mensa_id, lang,
+            name: plan.mensa().into(),
+            schema_version: SCHEMA_VERSION,
+        }, Some(
+            FindOneAndReplaceOptions::builder()
+                .upsert(true)
+                .return_document(ReturnDocument::After)
+            .build()
+        ), session).await?.expect("upsert should always create");
+
+        let Some(mensa_record_id) = mensa._id else {
+            tracing::error!("mensa does not have _id, could not store plan");
+            return Ok(())
+        };
+
+        let days: Vec<_> = plan.days().collect();
+        if days.is_empty() { return Ok(()) }
+
+        let updates = days.iter().map(|meal| Ok(doc! {
+            "q": {
+                "mensa_record_id": mensa_record_id,
+                "meal.date": meal.date.to_string(),
+            },
+            "u": bson::to_document(&MensaMealDay {
+                mensa_record_id,
+                meal: (*meal).clone(),
+                fetched_at: plan.fetched_at(),
+                schema_version: SCHEMA_VERSION,
+            })?,
+            "upsert": true,
+        })).collect::<Result<Vec<_>, StoreError>>()?;
+
+        // a single round-trip, unordered so one bad day does not stop the rest
+        // (outside of a transaction, inside one any failure aborts it)
+        let res = self.db.run_command_with_session(doc! {
+            "update": self.meals.name(),
+            "updates": updates,
+            "ordered": false,
+        }, None, session).await?;
+
+        // applied, but maybe not as durable as configured, so everything gets retried
+        let failed: Vec<_> = if let Ok(err) = res.get_document("writeConcernError") {
+            tracing::warn!("write concern failed for {} days: {err}", days.len());
+            days.iter().map(|v| v.date).collect()
+        } else {
+            res.get_array("writeErrors").into_iter()
+                .flatten()
+                .filter_map(|v| v.as_document()?.get_i32("index").ok())
+                .filter_map(|i| days.get(i as usize).map(|v| v.date))
+            .collect()
+        };
+        if !failed.is_empty() {
+            return Err(StoreError::Partial { failed, total: days.len() })
+        }
+
+        Ok(())
+    }
+
+    async fn find_days(
+        &self, filter: Document, options: FindOptions,
+    ) -> Result<Vec<StoredDay>, StoreError> {
+        Ok(self.meals.find(filter, options).await?
+            .map_ok(StoredDay::from)
+            .try_collect().await?)
+    }
+}
+
+impl From<MensaMealDay> for StoredDay {
+    fn from(v: MensaMealDay) -> Self {
+        Self { meal: v.meal, fetched_at: v.fetched_at }
+    }
+}
+
+/// `IllegalOperation`, transactions need a replica set or mongos
+fn is_standalone(err: &mongodb::error::Error) -> bool {
+    matches!(&*err.kind, ErrorKind::Command(v) if v.code == 20)
+}
+
+async fn has_index<T>(collection: &Collection<T>, name: &str) -> Result<bool, StoreError> {
+    match collection.list_index_names().await {
+        Ok(v) => Ok(v.iter().any(|v| v == name)),
+        // `NamespaceNotFound`, a new db
+        Err(err) if matches!(&*err.kind, ErrorKind::Command(v) if v.code == 26) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[async_trait::async_trait]
+impl MealStore for MealCollections {
+    async fn get_day(
+        &self, mensa_id: &str, lang: Option<&str>,
+        day: &NaiveDate,
+    ) -> Result<Option<StoredDay>, StoreError> {
+        let Some(mensa_id) = self.mensa_record_id(mensa_id, lang).await? else {
+            return Ok(None);
+        };
+
+        Ok(self.meals.find_one(doc! {
+            "mensa_record_id": mensa_id,
+            "meal.date": day.to_string(),
+        }, None).await?.map(StoredDay::from))
+    }
+
+    async fn get_range(
+        &self, mensa_id: &str, lang: Option<&str>,
+        from: &NaiveDate, to: &NaiveDate,
+    ) -> Result<Vec<StoredDay>, StoreError> {
+        let Some(mensa_id) = self.mensa_record_id(mensa_id, lang).await? else {
+            return Ok(Vec::new());
+        };
+
+        self.find_days(doc! {
+            "mensa_record_id": mensa_id,
+            "meal.date": { "$gte": from.to_string(), "$lte": to.to_string() },
+        }, FindOptions::builder().sort(doc! { "meal.date": 1 }).build()).await
+    }
+
+    async fn history(
+        &self, mensa_id: &str, lang: Option<&str>,
+        before: &NaiveDate, limit: usize,
+    ) -> Result<Vec<StoredDay>, StoreError> {
+        let Some(mensa_id) = self.mensa_record_id(mensa_id, lang).await? else {
+            return Ok(Vec::new());
+        };
+
+        self.find_days(doc! {
+            "mensa_record_id": mensa_id,
+            "meal.date": { "$lt": before.to_string() },
+        }, FindOptions::builder()
+            .sort(doc! { "meal.date": -1 })
+            .limit(limit as i64)
+        .build()).await
+    }
+
+    async fn list_mensas(&self) -> Result<Vec<StoredMensa>, StoreError> {
+        Ok(self.mensas.find(None, None).await?
+            .map_ok(|v| StoredMensa { mensa_id: v.mensa_id, lang: v.lang, name: v.name })
+            .try_collect().await?)
+    }
+
+    async fn prune(&self, before: &NaiveDate) -> Result<usize, StoreError> {
+        let res = self.meals.delete_many(doc! {
+            "meal.date": { "$lt": before.to_string() },
+        }, None).await?;
+        Ok(res.deleted_count as usize)
+    }
+
+    async fn store_plan(
+        &self, mensa_id: &str, lang: Option<&str>,
+        plan: &MealPlan,
+    ) -> Result<(), StoreError> {
+        let mut session = self.client.start_session(None).await?;
+        if !self.transactions.load(Ordering::Relaxed) {
+            return self.write_plan(&mut session, mensa_id, lang, plan).await
+        }
+
+        session.start_transaction(None).await?;
+        match self.write_plan(&mut session, mensa_id, lang, plan).await {
+            Ok(()) => Ok(session.commit_transaction().await?),
+            Err(StoreError::Mongo(err)) if is_standalone(&err) => {
+                tracing::warn!("mongodb does not support transactions (not a replica set), writing without them");
+                self.transactions.store(false, Ordering::Relaxed);
+                let _ = session.abort_transaction().await;
+                self.write_plan(&mut session, mensa_id, lang, plan).await
+            },
+            // nothing of the transaction was written
+            Err(StoreError::Partial { total, .. }) => {
+                let _ = session.abort_transaction().await;
+                Err(StoreError::Partial { failed: plan.days().map(|v| v.date).collect(), total })
+            },
+            Err(err) => {
+                let _ = session.abort_transaction().await;
+                Err(err)
+            },
+        }
+    }
+}
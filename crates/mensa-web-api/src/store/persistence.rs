@@ -0,0 +1,147 @@
+use std::{collections::BTreeMap, sync::{Arc, Mutex}};
+
+use chrono::{DateTime, Utc};
+use mensa_meal_api::{MealPlan, MealPlans};
+use serde::Serialize;
+
+use crate::clock::Clock;
+
+use super::Store;
+
+/// writes plans to the store and remembers the ones that failed,
+/// so they can be retried once the db is back
+#[derive(Clone)]
+pub struct Persistence {
+    store: Store,
+    clock: Clock,
+    mensas: Arc<Mutex<BTreeMap<(String, String), Entry>>>,
+}
+
+struct Entry {
+    status: PersistenceStatus,
+    /// every day that still has to be written
+    pending: Option<MealPlan>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PersistenceStatus {
+    pub mensa_id: String,
+    pub lang: String,
+    pub state: PersistenceState,
+    pub pending_days: usize,
+    /// failed attempts since the last successful write
+    pub failures: u32,
+    pub last_stored: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PersistenceState {
+    Stored,
+    /// queued for a retry
+    Pending,
+}
+
+impl Persistence {
+    pub fn new(store: Store, clock: Clock) -> Self {
+        Self { store, clock, mensas: Default::default() }
+    }
+
+    pub fn store(&self) -> &Store { &self.store }
+
+    /// stores `plan`, together with anything still pending for the mensa
+    pub async fn write(&self, mensa_id: &str, lang: Option<&str>, plan: MealPlan) {
+        let key = (mensa_id.to_string(), lang.unwrap_or(MealPlans::DEFAULT_LANG).to_string());
+        let plan = match self.take_pending(&key) {
+            Some(pending) => merge(plan, &pending),
+            None => plan,
+        };
+
+        let res = self.store.store_plan(&key.0, Some(&key.1), &plan).await;
+
+        let mut mensas = self.mensas.lock().unwrap();
+        let entry = mensas.entry(key.clone()).or_insert_with(|| Entry {
+            status: PersistenceStatus {
+                mensa_id: key.0.clone(),
+                lang: key.1.clone(),
+                state: PersistenceState::Stored,
+                pending_days: 0,
+                failures: 0,
+                last_stored: None,
+                last_error: None,
+            },
+            pending: None,
+        });
+
+        match res {
+            Ok(()) => {
+                entry.status.last_stored = Some(self.clock.now().with_timezone(&Utc));
+                // unless a concurrent write failed in the meantime
+                if entry.pending.is_none() {
+                    entry.status.state = PersistenceState::Stored;
+                    entry.status.pending_days = 0;
+                    entry.status.failures = 0;
+                    entry.status.last_error = None;
+                }
+            },
+            Err(err) => {
+                let plan = match entry.pending.take() {
+                    Some(newer) => merge(newer, &plan),
+                    None => plan,
+                };
+                tracing::error!(
+                    "could not store {} (in {}), queued {} days for a retry: {err}",
+                    key.0, key.1, plan.days().len(),
+                );
+                entry.status.state = PersistenceState::Pending;
+                entry.status.pending_days = plan.days().len();
+                entry.status.failures += 1;
+                entry.status.last_error = Some(err.to_string());
+                entry.pending = Some(plan);
+            },
+        }
+    }
+
+    /// writes everything that failed before
+    pub async fn retry(&self) {
+        let pending: Vec<_> = self.mensas.lock().unwrap().iter()
+            .filter(|(_, v)| v.pending.is_some())
+            .map(|(k, _)| k.clone())
+        .collect();
+
+        for (mensa_id, lang) in pending {
+            tracing::info!("retrying to store {mensa_id} (in {lang})");
+            if let Some(plan) = self.take_pending(&(mensa_id.clone(), lang.clone())) {
+                self.write(&mensa_id, Some(&lang), plan).await;
+            }
+        }
+    }
+
+    fn take_pending(&self, key: &(String, String)) -> Option<MealPlan> {
+        self.mensas.lock().unwrap().get_mut(key)?.pending.take()
+    }
+
+    pub fn pending_days(&self) -> usize {
+        self.mensas.lock().unwrap().values()
+            .filter_map(|v| v.pending.as_ref())
+            .map(|v| v.days().len())
+        .sum()
+    }
+
+    pub fn statuses(&self) -> Vec<PersistenceStatus> {
+        self.mensas.lock().unwrap().values()
+            .map(|v| v.status.clone())
+        .collect()
+    }
+}
+
+/// `newer` with the days of `older` it does not have
+fn merge(mut newer: MealPlan, older: &MealPlan) -> MealPlan {
+    for day in older.days() {
+        if newer.get_day_internal(&day.date).is_none() {
+            newer.add_day(day.date, day.clone());
+        }
+    }
+    newer
+}
@@ -0,0 +1,189 @@
+use std::{path::Path, sync::{Arc, Mutex}};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use mensa_meal_api::{MealPlan, MealPlans};
+use rusqlite::{params, params_from_iter, types::Value, Connection, OptionalExtension, Row};
+
+use super::{MealStore, StoreError, StoredDay, StoredMensa};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS mensas (
+        id INTEGER PRIMARY KEY,
+        mensa_id TEXT NOT NULL,
+        lang TEXT NOT NULL,
+        name TEXT NOT NULL,
+        UNIQUE (mensa_id, lang)
+    );
+    CREATE TABLE IF NOT EXISTS meals (
+        mensa INTEGER NOT NULL REFERENCES mensas (id),
+        date TEXT NOT NULL,
+        meal TEXT NOT NULL,
+        -- rfc 3339
+        fetched_at TEXT,
+        PRIMARY KEY (mensa, date)
+    );
+";
+
+/// embedded store, days are kept as json
+#[derive(Clone)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+    /// opens (or creates) the database at `path`, `:memory:` works too
+    pub fn open(path: &Path) -> Result<Self, StoreError> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(SCHEMA)?;
+        // databases from before `fetched_at`
+        if conn.prepare("SELECT fetched_at FROM meals LIMIT 0").is_err() {
+            conn.execute_batch("ALTER TABLE meals ADD COLUMN fetched_at TEXT")?;
+        }
+        Ok(Self { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    /// runs `f` on the blocking pool
+    async fn with_conn<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Connection) -> Result<T, StoreError> + Send + 'static,
+    ) -> Result<T, StoreError> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&mut conn.lock().unwrap())).await?
+    }
+
+    /// `sql` has to select the `meal` and `fetched_at` columns
+    async fn query_days(
+        &self, sql: &'static str, params: Vec<Value>,
+    ) -> Result<Vec<StoredDay>, StoreError> {
+        self.with_conn(move |conn| {
+            let mut stmt = conn.prepare_cached(sql)?;
+            let rows = stmt.query_map(params_from_iter(params), columns)?;
+
+            rows.map(|v| stored_day(v?)).collect()
+        }).await
+    }
+}
+
+fn columns(row: &Row) -> rusqlite::Result<(String, Option<String>)> {
+    Ok((row.get(0)?, row.get(1)?))
+}
+
+fn stored_day((meal, fetched_at): (String, Option<String>)) -> Result<StoredDay, StoreError> {
+    Ok(StoredDay {
+        meal: serde_json::from_str(&meal)?,
+        fetched_at: fetched_at.and_then(|v| DateTime::parse_from_rfc3339(&v).ok())
+            .map(|v| v.with_timezone(&Utc)),
+    })
+}
+
+fn lang_or_default(lang: Option<&str>) -> String {
+    lang.unwrap_or(MealPlans::DEFAULT_LANG).into()
+}
+
+#[async_trait::async_trait]
+impl MealStore for SqliteStore {
+    async fn store_plan(
+        &self, mensa_id: &str, lang: Option<&str>, plan: &MealPlan,
+    ) -> Result<(), StoreError> {
+        let (mensa_id, lang) = (mensa_id.to_string(), lang_or_default(lang));
+        let name = plan.mensa().to_string();
+        let fetched_at = plan.fetched_at().map(|v| v.to_rfc3339());
+        let days = plan.days()
+            .map(|v| Ok((v.date.to_string(), serde_json::to_string(v)?)))
+        .collect::<Result<Vec<_>, StoreError>>()?;
+
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            let id: i64 = tx.query_row(
+                "INSERT INTO mensas (mensa_id, lang, name) VALUES (?1, ?2, ?3)
+                ON CONFLICT (mensa_id, lang) DO UPDATE SET name = excluded.name
+                RETURNING id",
+                params![mensa_id, lang, name],
+                |row| row.get(0),
+            )?;
+
+            {
+                let mut stmt = tx.prepare_cached(
+                    "INSERT OR REPLACE INTO meals (mensa, date, meal, fetched_at)
+                    VALUES (?1, ?2, ?3, ?4)",
+                )?;
+                for (date, meal) in days {
+                    stmt.execute(params![id, date, meal, fetched_at])?;
+                }
+            }
+
+            Ok(tx.commit()?)
+        }).await
+    }
+
+    async fn get_day(
+        &self, mensa_id: &str, lang: Option<&str>, day: &NaiveDate,
+    ) -> Result<Option<StoredDay>, StoreError> {
+        let (mensa_id, lang, day) = (mensa_id.to_string(), lang_or_default(lang), day.to_string());
+
+        self.with_conn(move |conn| {
+            let row = conn.query_row(
+                "SELECT meal, fetched_at FROM meals JOIN mensas ON meals.mensa = mensas.id
+                WHERE mensas.mensa_id = ?1 AND mensas.lang = ?2 AND meals.date = ?3",
+                params![mensa_id, lang, day],
+                columns,
+            ).optional()?;
+
+            row.map(stored_day).transpose()
+        }).await
+    }
+
+    async fn get_range(
+        &self, mensa_id: &str, lang: Option<&str>,
+        from: &NaiveDate, to: &NaiveDate,
+    ) -> Result<Vec<StoredDay>, StoreError> {
+        self.query_days(
+            "SELECT meal, fetched_at FROM meals JOIN mensas ON meals.mensa = mensas.id
+            WHERE mensas.mensa_id = ?1 AND mensas.lang = ?2
+                AND meals.date >= ?3 AND meals.date <= ?4
+            ORDER BY meals.date",
+            vec![
+                mensa_id.to_string().into(), lang_or_default(lang).into(),
+                from.to_string().into(), to.to_string().into(),
+            ],
+        ).await
+    }
+
+    async fn history(
+        &self, mensa_id: &str, lang: Option<&str>,
+        before: &NaiveDate, limit: usize,
+    ) -> Result<Vec<StoredDay>, StoreError> {
+        self.query_days(
+            "SELECT meal, fetched_at FROM meals JOIN mensas ON meals.mensa = mensas.id
+            WHERE mensas.mensa_id = ?1 AND mensas.lang = ?2 AND meals.date < ?3
+            ORDER BY meals.date DESC
+            LIMIT ?4",
+            vec![
+                mensa_id.to_string().into(), lang_or_default(lang).into(),
+                before.to_string().into(), (limit as i64).into(),
+            ],
+        ).await
+    }
+
+    async fn list_mensas(&self) -> Result<Vec<StoredMensa>, StoreError> {
+        self.with_conn(|conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT mensa_id, lang, name FROM mensas ORDER BY mensa_id, lang",
+            )?;
+            let rows = stmt.query_map([], |row| Ok(StoredMensa {
+                mensa_id: row.get(0)?,
+                lang: row.get(1)?,
+                name: row.get(2)?,
+            }))?;
+
+            Ok(rows.collect::<Result<_, _>>()?)
+        }).await
+    }
+
+    async fn prune(&self, before: &NaiveDate) -> Result<usize, StoreError> {
+        let before = before.to_string();
+        self.with_conn(move |conn| {
+            Ok(conn.execute("DELETE FROM meals WHERE date < ?1", params![before])?)
+        }).await
+    }
+}
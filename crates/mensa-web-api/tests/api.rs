@@ -0,0 +1,1104 @@
+mod common;
+
+use std::{sync::Arc, time::{Duration, Instant}};
+
+use axum::http::StatusCode;
+use chrono::{NaiveDate, Utc, Weekday};
+use common::*;
+use futures::future::join_all;
+use serde_json::Value;
+use mensa_web_api::{
+    config::{Config, JobConfig, JobKind},
+    jobs::JobRun,
+    mock_upstream::MockBehaviour,
+    store::{MealStore, MemoryStore},
+};
+
+fn date(s: &str) -> NaiveDate { s.parse().unwrap() }
+
+#[tokio::test]
+async fn meals_today_from_upstream() {
+    let app = app(noon(), None).await;
+
+    let (status, body) = app.get("/api/meals?mensa=321&lang=en").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["date"], "2024-05-22");
+    assert_eq!(body["state"]["kind"], "open");
+    assert!(body["categories"]["Main dishes"].is_array());
+}
+
+#[tokio::test]
+async fn today_uses_berlin_time() {
+    // 00:30 in berlin, still the 21st in utc
+    let app = app("2024-05-21T22:30:00Z".parse().unwrap(), None).await;
+
+    let (status, body) = app.get("/api/meals?mensa=321&day=today").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["date"], "2024-05-22");
+
+    let (_, body) = app.get("/api/meals?mensa=321&day=tomorrow").await;
+    assert_eq!(body["date"], "2024-05-23");
+}
+
+#[tokio::test]
+async fn weekday_and_date_params() {
+    let app = app(noon(), None).await;
+
+    let (_, body) = app.get("/api/meals?mensa=321&day=fri").await;
+    assert_eq!(body["date"], "2024-05-24");
+
+    let (_, body) = app.get("/api/meals?mensa=321&day=2024-05-28").await;
+    assert_eq!(body["date"], "2024-05-28");
+    assert_eq!(body["state"]["kind"], "open");
+}
+
+#[tokio::test]
+async fn plan_is_cached() {
+    let app = app(noon(), None).await;
+
+    let (status, body) = app.get("/api/meals/plan?mensa=321&lang=de").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["mensa_name"], "Mensa FU II");
+    assert_eq!(body["days"].as_array().unwrap().len(), 9);
+
+    app.get("/api/meals/plan?mensa=321&lang=de").await;
+    app.get("/api/meals?mensa=321&lang=de").await;
+    app.get("/api/meals?mensa=321&lang=de&day=tomorrow").await;
+    assert_eq!(app.mock.requests(), 1);
+
+    // languages are cached separately
+    app.get("/api/meals?mensa=321&lang=en").await;
+    assert_eq!(app.mock.requests(), 2);
+}
+
+#[tokio::test]
+async fn freshness_headers() {
+    let app = app(noon(), None).await;
+
+    let (status, headers, body) = app.get_with_headers("/api/meals?mensa=321").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["age"], "0");
+    assert_eq!(headers["x-data-fetched-at"], "2024-05-22T10:00:00+00:00");
+    assert!(!headers.contains_key("warning"));
+    assert_eq!(body["freshness"]["source"], "upstream");
+    assert!(body["freshness"]["upstream"].as_str().unwrap().starts_with("http://127.0.0.1"));
+
+    app.advance(chrono::Duration::minutes(5));
+    let (_, headers, later) = app.get_with_headers("/api/meals?mensa=321").await;
+    assert_eq!(headers["age"], "300");
+    assert_eq!(headers["x-data-fetched-at"], "2024-05-22T10:00:00+00:00");
+    // only the headers change with the age
+    assert_eq!(later, body);
+}
+
+#[tokio::test]
+async fn conditional_requests() {
+    let app = app_with(noon(), None, |c| c.cache.ttl_secs = 24 * 60 * 60).await;
+
+    let (status, headers, _) = app.get_with_headers("/api/meals?mensa=321").await;
+    assert_eq!(status, StatusCode::OK);
+    let etag = headers["etag"].to_str().unwrap().to_string();
+    // strong, the age is only in the headers
+    assert!(etag.starts_with('"'), "{etag}");
+    assert_eq!(headers["last-modified"], "Wed, 22 May 2024 10:00:00 GMT");
+    // until the nightly refresh at 00:01 in berlin
+    assert_eq!(headers["cache-control"], "public, max-age=43260");
+
+    let (status, headers, body) = app.get_with("/api/meals?mensa=321", &[("if-none-match", &etag)]).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    assert_eq!(headers["etag"], etag.as_str());
+    assert_eq!(headers["content-language"], "en");
+    assert_eq!(body, "");
+
+    // `If-None-Match` compares weakly
+    let weak = format!("W/{etag}");
+    let (status, ..) = app.get_with("/api/meals?mensa=321", &[("if-none-match", &weak)]).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+
+    let (status, ..) = app.get_with("/api/meals?mensa=321", &[("if-none-match", "\"nope\"")]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, ..) = app.get_with("/api/meals?mensa=321", &[
+        ("if-modified-since", "Wed, 22 May 2024 10:00:00 GMT"),
+    ]).await;
+    assert_eq!(status, StatusCode::NOT_MODIFIED);
+    let (status, ..) = app.get_with("/api/meals?mensa=321", &[
+        ("if-modified-since", "Wed, 22 May 2024 09:59:59 GMT"),
+    ]).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // same meals, same etag, even though they were fetched again
+    app.advance(chrono::Duration::minutes(1));
+    app.app.refresh().await;
+    let (_, headers, _) = app.get_with_headers("/api/meals?mensa=321").await;
+    assert_eq!(headers["etag"], etag.as_str());
+    assert_eq!(headers["last-modified"], "Wed, 22 May 2024 10:01:00 GMT");
+    assert_eq!(app.mock.requests(), 2);
+
+    let (_, headers, _) = app.get_with_headers("/api/meals?mensa=321&day=tomorrow").await;
+    assert_ne!(headers["etag"], etag.as_str());
+}
+
+#[tokio::test]
+async fn cache_control_follows_the_ttl() {
+    let app = app_with(noon(), None, |c| c.cache.ttl_secs = 60 * 60).await;
+
+    let (_, headers, _) = app.get_with_headers("/api/meals/plan?mensa=321").await;
+    assert_eq!(headers["cache-control"], "public, max-age=3600");
+
+    app.advance(chrono::Duration::hours(2));
+    let (_, headers, _) = app.get_with_headers("/api/meals/plan?mensa=321").await;
+    assert_eq!(headers["cache-control"], "public, max-age=0");
+}
+
+#[tokio::test]
+async fn stale_plans_are_served_and_refreshed() {
+    let app = app_with(noon(), None, |c| c.cache.ttl_secs = 60).await;
+
+    app.get("/api/meals?mensa=321").await;
+    assert_eq!(app.mock.requests(), 1);
+
+    app.advance(chrono::Duration::seconds(61));
+    // answered from memory, the refresh runs in the background
+    let (status, headers, body) = app.get_with_headers("/api/meals?mensa=321").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["age"], "61");
+    assert_eq!(headers["warning"], "110 - \"Response is Stale\"");
+    assert_eq!(body["freshness"]["source"], "upstream");
+
+    app.app.settle().await;
+    assert_eq!(app.mock.requests(), 2);
+
+    let (_, headers, _) = app.get_with_headers("/api/meals?mensa=321").await;
+    assert_eq!(headers["age"], "0");
+    assert!(!headers.contains_key("warning"));
+    assert_eq!(app.mock.requests(), 2);
+}
+
+#[tokio::test]
+async fn stale_plans_without_the_day_are_refreshed() {
+    let app = app_with(noon(), None, |c| c.cache.ttl_secs = 60).await;
+    app.get("/api/meals?mensa=321").await;
+
+    // upstream publishes a later week (the one the real clock is in), which is now
+    app.mock.set_behaviour(MockBehaviour { shift_dates: true, ..Default::default() });
+    let monday = Utc::now().date_naive().week(Weekday::Mon).first_day();
+    app.advance(monday.and_hms_opt(10, 0, 0).unwrap().and_utc() + chrono::Days::new(2) - noon());
+
+    // `nearby` only looks at the day, the stale plan does not have it
+    let menu = || async {
+        let (_, body) = app.get("/api/v1/mensas/nearby?lat=52.45&lon=13.29&menu=true").await;
+        body.as_array().unwrap().iter().find(|v| v["id"] == "321").unwrap()["menu"].clone()
+    };
+    assert!(menu().await.is_null());
+    app.app.settle().await;
+
+    let menu = menu().await;
+    assert_eq!(menu["state"]["kind"], "open");
+    assert!(!menu["categories"].as_object().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn upstream_errors() {
+    let app = app(noon(), None).await;
+    app.mock.set_behaviour(MockBehaviour { error_rate: 1.0, ..Default::default() });
+
+    let (status, body) = app.get("/api/meals/plan?mensa=321").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body["error"]["code"], "upstream_error");
+
+    let requests = app.mock.requests();
+    let (status, _) = app.get("/api/meals?mensa=321").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    // upstream is asked once per request, not again to explain the missing day
+    assert_eq!(app.mock.requests(), requests + 1);
+}
+
+#[tokio::test]
+async fn circuit_breaker() {
+    let app = app_with(noon(), None, |c| {
+        c.upstream.breaker_failures = 2;
+        c.upstream.breaker_cooldown_secs = 1;
+    }).await;
+    app.mock.set_behaviour(MockBehaviour { error_rate: 1.0, ..Default::default() });
+
+    for _ in 0..2 {
+        let (status, _) = app.get("/api/meals/plan?mensa=321").await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+    }
+    let requests = app.mock.requests();
+
+    // open, upstream is not asked
+    let (status, body) = app.get("/api/meals/plan?mensa=321").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(body["error"]["code"], "upstream_unavailable");
+    assert_eq!(app.mock.requests(), requests);
+    let (_, body) = app.admin("/api/admin/upstream").await;
+    assert_eq!(body["state"], "open");
+
+    // a single probe closes it again
+    app.mock.set_behaviour(MockBehaviour::default());
+    app.advance(chrono::Duration::seconds(1));
+    let (status, _) = app.get("/api/meals/plan?mensa=321").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.mock.requests(), requests + 1);
+    let (_, body) = app.admin("/api/admin/upstream").await;
+    assert_eq!(body["state"], "closed");
+}
+
+#[tokio::test]
+async fn cancelled_probes_do_not_keep_the_circuit_open() {
+    let app = app_with(noon(), None, |c| {
+        c.upstream.breaker_failures = 1;
+        c.upstream.breaker_cooldown_secs = 60;
+    }).await;
+    app.mock.fail_next(1);
+    let (status, _) = app.get("/api/meals/plan?mensa=321").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+    // the client gives up on the probe, which still finishes
+    app.advance(chrono::Duration::seconds(60));
+    app.mock.set_behaviour(MockBehaviour { latency: Duration::from_millis(500), ..Default::default() });
+    let probe = tokio::time::timeout(Duration::from_millis(100), app.get("/api/meals/plan?mensa=321"));
+    assert!(probe.await.is_err());
+    app.app.settle().await;
+
+    let (_, body) = app.admin("/api/admin/upstream").await;
+    assert_eq!(body["state"], "closed");
+    let (status, _) = app.get("/api/meals/plan?mensa=322").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.mock.requests(), 3);
+}
+
+#[tokio::test]
+async fn stale_plans_are_served_while_upstream_is_down() {
+    let app = app_with(noon(), None, |c| {
+        c.cache.ttl_secs = 60;
+        c.upstream.breaker_failures = 1;
+    }).await;
+    app.get("/api/meals?mensa=321").await;
+
+    app.mock.set_behaviour(MockBehaviour { error_rate: 1.0, ..Default::default() });
+    app.advance(chrono::Duration::minutes(10));
+    for _ in 0..3 {
+        let (status, headers, body) = app.get_with_headers("/api/meals?mensa=321").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["warning"], "110 - \"Response is Stale\"");
+        assert_eq!(body["freshness"]["source"], "upstream");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    // the first refresh opened the breaker
+    assert_eq!(app.mock.requests(), 2);
+}
+
+#[tokio::test]
+async fn malformed_upstream_response() {
+    let app = app(noon(), None).await;
+    app.mock.set_behaviour(MockBehaviour { malformed_rate: 1.0, ..Default::default() });
+
+    let (status, _) = app.get("/api/meals/plan?mensa=321").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+
+    // not cached, recovers once upstream does
+    app.mock.set_behaviour(MockBehaviour::default());
+    let (status, _) = app.get("/api/meals/plan?mensa=321").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn slow_upstream() {
+    let app = app(noon(), None).await;
+    app.mock.set_behaviour(MockBehaviour {
+        latency: Duration::from_millis(200),
+        ..Default::default()
+    });
+
+    let (status, _) = app.get("/api/meals?mensa=321").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn concurrent_misses_share_one_fetch() {
+    let app = app(noon(), None).await;
+    app.mock.set_behaviour(MockBehaviour {
+        latency: Duration::from_millis(200),
+        ..Default::default()
+    });
+
+    let responses = join_all((0..10).map(|i| {
+        let uri = if i % 2 == 0 { "/api/meals?mensa=321" } else { "/api/meals/plan?mensa=321&lang=en" };
+        app.get(uri)
+    })).await;
+    assert!(responses.iter().all(|(status, _)| *status == StatusCode::OK));
+    assert_eq!(app.mock.requests(), 1);
+
+    // errors are shared too, and not cached
+    app.mock.set_behaviour(MockBehaviour {
+        latency: Duration::from_millis(200),
+        error_rate: 1.0,
+        ..Default::default()
+    });
+    let responses = join_all((0..10).map(|_| app.get("/api/meals/plan?mensa=322"))).await;
+    assert!(responses.iter().all(|(status, _)| *status == StatusCode::BAD_GATEWAY));
+    assert_eq!(app.mock.requests(), 2);
+
+    app.get("/api/meals/plan?mensa=322").await;
+    assert_eq!(app.mock.requests(), 3);
+}
+
+#[tokio::test]
+async fn upstream_timeout() {
+    let app = app_with(noon(), None, |c| c.upstream.timeout_ms = 100).await;
+    app.mock.set_behaviour(MockBehaviour {
+        latency: Duration::from_secs(30),
+        ..Default::default()
+    });
+
+    // far from the latency, however slow the machine
+    let start = Instant::now();
+    let (status, _) = app.get("/api/meals?mensa=321").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert!(start.elapsed() < Duration::from_secs(10), "took {:?}", start.elapsed());
+}
+
+#[tokio::test]
+async fn invalid_dates() {
+    let app = app(noon(), None).await;
+
+    for day in ["someday", "2024-02-30", "2024-13-01"] {
+        let (status, _) = app.get(&format!("/api/meals?mensa=321&day={day}")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{day}");
+    }
+    assert_eq!(app.mock.requests(), 0);
+}
+
+#[tokio::test]
+async fn unknown_mensa() {
+    let app = app_with(noon(), None, |c| c.directory.strict = true).await;
+
+    let (status, body) = app.get("/api/meals?mensa=32").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["code"], "unknown_mensa");
+    assert!(body["error"]["details"]["suggestions"].as_array().unwrap().iter().any(|v| v["id"] == "321"));
+
+    let (status, _) = app.get("/api/meals/plan?mensa=nope").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = app.get("/api/meals").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    assert_eq!(app.mock.requests(), 0);
+}
+
+#[tokio::test]
+async fn mensa_directory() {
+    let app = app(noon(), None).await;
+
+    let (status, body) = app.get("/api/v1/mensas").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["version"].is_u64());
+    assert_eq!(body["mensas"].as_array().unwrap().len(), 13);
+
+    let (_, body) = app.get("/api/v1/mensas?campus=tu%20charlottenburg").await;
+    let mensas = body["mensas"].as_array().unwrap();
+    assert_eq!(mensas.len(), 2);
+    assert!(mensas.iter().all(|v| v["campus"] == "TU Charlottenburg"));
+
+    let (_, body) = app.get("/api/v1/mensas?q=nope").await;
+    assert!(body["mensas"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn mensas_outside_the_directory_are_passed_through() {
+    let app = app(noon(), None).await;
+
+    // 900 is not in the bundled directory, but the (synthetic) upstream knows it
+    let (status, body) = app.get("/api/meals/plan?mensa=900&lang=de").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["days"].is_array());
+    assert_eq!(app.mock.requests(), 1);
+}
+
+#[tokio::test]
+async fn error_responses() {
+    let app = app(noon(), None).await;
+
+    let (status, body) = app.get("/api/meals?mensa=321&day=someday&lang=de").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "invalid_query");
+    assert_eq!(body["error"]["message"], "Die Anfrageparameter sind ungültig.");
+    assert!(body["error"]["details"]["reason"].is_string());
+
+    let (status, body) = app.get("/api/mensas/nearby?lat=52.45&lon=13.29&with=tasty").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "invalid_filter");
+    assert_eq!(body["error"]["message"], "The filter contains unknown attributes or allergens.");
+
+    let (status, body) = app.get("/api/mensas/nearby?lat=100&lon=0").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "invalid_coordinates");
+
+    let (status, body) = app.get("/api/mensas/%FF/status?lang=de").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "invalid_path");
+    assert_eq!(body["error"]["message"], "Der Pfad ist ungültig.");
+
+    let (status, body) = app.get("/api/nope").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["code"], "not_found");
+}
+
+#[tokio::test]
+async fn closed_days() {
+    let app = app(noon(), None).await;
+
+    let (status, body) = app.get("/api/meals?mensa=321&day=2024-05-25").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["state"]["kind"], "closed");
+    assert_eq!(body["state"]["reason"], "regular_hours");
+
+    let (_, body) = app.get("/api/meals?mensa=321&day=2024-05-20").await;
+    assert_eq!(body["state"]["reason"]["holiday"], "Pfingstmontag");
+
+    let (_, body) = app.get("/api/meals?mensa=322&day=2024-05-29").await;
+    assert_eq!(body["state"]["reason"], "no_meals");
+
+    let (_, body) = app.get("/api/meals?mensa=321&day=2024-06-03").await;
+    assert_eq!(body["state"]["kind"], "not_yet_published");
+}
+
+#[tokio::test]
+async fn storage_fallback_for_past_days() {
+    let day = date("2024-05-14");
+    let store = store_with(&stored_plan(day)).await;
+    let app = app(noon(), Some(store)).await;
+
+    let (status, body) = app.get("/api/meals?mensa=321&day=2024-05-14").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["date"], "2024-05-14");
+    assert_eq!(body["categories"]["Essen"][0]["title"], "Eintopf aus dem Archiv");
+}
+
+#[tokio::test]
+async fn storage_fallback_when_upstream_is_down() {
+    let mut plan = stored_plan(date("2024-05-22"));
+    plan.set_fetched("https://upstream.example", noon() - chrono::Duration::hours(2));
+    let store = store_with(&plan).await;
+    let app = app(noon(), Some(store)).await;
+    app.mock.set_behaviour(MockBehaviour { error_rate: 1.0, ..Default::default() });
+
+    let (status, headers, body) = app.get_with_headers("/api/meals?mensa=321").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["categories"]["Essen"][0]["title"], "Eintopf aus dem Archiv");
+    assert_eq!(body["freshness"]["source"], "store");
+    assert!(headers.contains_key("warning"));
+    // as old as when it was fetched, not when it was read back
+    assert_eq!(headers["age"], "7200");
+    assert_eq!(headers["last-modified"], "Wed, 22 May 2024 08:00:00 GMT");
+
+    let (status, headers, body) = app.get_with_headers("/api/meals/plan?mensa=321").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["mensa_name"], "Mensa FU II");
+    assert_eq!(body["freshness"]["source"], "store");
+    assert!(headers.contains_key("warning"));
+    assert_eq!(headers["x-data-fetched-at"], "2024-05-22T08:00:00+00:00");
+    assert_eq!(headers["age"], "7200");
+}
+
+#[tokio::test]
+async fn fetched_plans_are_stored() {
+    let store = Arc::new(MemoryStore::default());
+    let app = app(noon(), Some(store.clone())).await;
+
+    app.get("/api/meals?mensa=321").await;
+
+    // storing happens in the background
+    let (from, to) = (date("2024-05-01"), date("2024-06-30"));
+    let mut stored = Vec::new();
+    for _ in 0..50 {
+        stored = store.get_range("321", None, &from, &to).await.unwrap();
+        if !stored.is_empty() { break }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(stored.len(), 9);
+}
+
+#[tokio::test]
+async fn admin_needs_the_token() {
+    let app = app(noon(), None).await;
+    for auth in [None, Some("Bearer nope"), Some("test-token")] {
+        let headers: Vec<_> = auth.map(|v| ("authorization", v)).into_iter().collect();
+        let (status, headers, body) = app.get_with("/api/admin/jobs", &headers).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED, "{auth:?}");
+        assert_eq!(headers["www-authenticate"], "Bearer");
+        assert_eq!(body["error"]["code"], "unauthorized");
+    }
+    assert_eq!(app.admin("/api/admin/jobs").await.0, StatusCode::OK);
+
+    // no token, no admin endpoints
+    let app = app_with(noon(), None, |c| c.server.admin_token = None).await;
+    let (status, body) = app.admin("/api/admin/jobs").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["code"], "not_found");
+}
+
+#[tokio::test]
+async fn persistence_status() {
+    let without_db = app(noon(), None).await;
+    let (status, body) = without_db.admin("/api/admin/persistence").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["enabled"], false);
+
+    let app = app(noon(), Some(Arc::new(MemoryStore::default()))).await;
+    app.get("/api/meals?mensa=321").await;
+
+    // storing happens in the background
+    let mut body = Value::Null;
+    for _ in 0..50 {
+        body = app.admin("/api/admin/persistence").await.1;
+        if body["mensas"][0]["state"] == "stored" { break }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(body["enabled"], true);
+    assert_eq!(body["pending_days"], 0);
+    assert_eq!(body["mensas"][0]["mensa_id"], "321");
+    assert_eq!(body["mensas"][0]["lang"], "en");
+    assert_eq!(body["mensas"][0]["state"], "stored");
+    // from the app's clock
+    assert_eq!(body["mensas"][0]["last_stored"], "2024-05-22T10:00:00Z");
+}
+
+#[tokio::test]
+async fn warm_start_from_snapshot() {
+    let path = std::env::temp_dir().join(format!("mensa-snapshot-{}.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let before = app_with(noon(), None, |c| c.cache.snapshot = Some(path.clone())).await;
+    before.get("/api/meals?mensa=321").await;
+    before.app.shutdown().await;
+
+    let after = app_with(noon(), None, |c| c.cache.snapshot = Some(path.clone())).await;
+    after.mock.set_behaviour(MockBehaviour { error_rate: 1.0, ..Default::default() });
+    assert_eq!(after.app.tracked(), [("321".to_string(), "en".to_string())]);
+
+    let (status, body) = after.get("/api/meals?mensa=321").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["categories"].as_object().is_some_and(|v| !v.is_empty()));
+    assert_eq!(after.mock.requests(), 0);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn tracked_mensas_are_seeded_from_store() {
+    let store = store_with(&stored_plan(date("2024-05-20"))).await;
+    let recent = app(noon(), Some(store)).await;
+    assert_eq!(recent.app.tracked(), [("321".to_string(), "en".to_string())]);
+
+    // the nightly job refreshes it, even though nobody asked for it yet
+    recent.app.refresh().await;
+    assert_eq!(recent.mock.requests(), 1);
+
+    // too old to be worth refreshing
+    let store = store_with(&stored_plan(date("2024-04-01"))).await;
+    let old = app(noon(), Some(store)).await;
+    assert!(old.app.tracked().is_empty());
+}
+
+#[tokio::test]
+async fn prefetched_mensas_are_refreshed() {
+    let app = app_with(noon(), None, |c| {
+        c.prefetch.mensas = vec!["322".into(), "nope".into()];
+        c.prefetch.langs = vec!["de".into(), "en".into()];
+        c.directory.strict = true;
+    }).await;
+
+    // ids outside the directory are skipped
+    assert_eq!(app.app.tracked(), [
+        ("322".to_string(), "de".to_string()),
+        ("322".to_string(), "en".to_string()),
+    ]);
+
+    app.app.refresh().await;
+    assert_eq!(app.mock.requests(), 2);
+
+    // served from memory now
+    let (status, body) = app.get("/api/meals?mensa=322&lang=de&day=2024-05-28").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["categories"]["Essen"].is_array());
+    assert_eq!(app.mock.requests(), 2);
+}
+
+#[tokio::test]
+async fn prefetch_runs_at_startup() {
+    // without any scheduled jobs
+    let app = app_prefetching(noon(), |c| {
+        c.prefetch.mensas = vec!["322".into()];
+        c.jobs.clear();
+    }).await;
+
+    let mut body = Value::Null;
+    for _ in 0..50 {
+        body = app.admin("/api/admin/fetches").await.1;
+        if body[0]["last_success"].is_string() { break }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(body[0]["mensa_id"], "322");
+    assert_eq!(app.mock.requests(), 1);
+
+    let (status, _) = app.get("/api/meals?mensa=322&day=2024-05-28").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.mock.requests(), 1);
+}
+
+#[tokio::test]
+async fn job_registry() {
+    let app = app_with(noon(), Some(store_with(&stored_plan(date("2024-05-15"))).await), |c| {
+        c.jobs.push(JobConfig { keep_days: Some(5), ..JobConfig::new(JobKind::Retention, "0 0 3 * * *") });
+    }).await;
+
+    let (status, body) = app.admin("/api/admin/jobs").await;
+    assert_eq!(status, StatusCode::OK);
+    let names: Vec<_> = body.as_array().unwrap().iter().map(|v| v["name"].as_str().unwrap()).collect();
+    assert_eq!(names, ["cleanup", "refresh", "retention", "retry_writes"]);
+    // the test app does not schedule anything
+    assert_eq!(body[1]["scheduled"], false);
+    assert_eq!(body[1]["runs"], 0);
+
+    // 321 is tracked through the store
+    assert_eq!(app.app.run_job("refresh").await, JobRun::Done);
+    assert_eq!(app.app.run_job("retention").await, JobRun::Done);
+    assert_eq!(app.app.run_job("nope").await, JobRun::NotFound);
+
+    let (_, body) = app.admin("/api/admin/jobs").await;
+    assert_eq!(body[1]["runs"], 1);
+    assert_eq!(body[1]["last_outcome"], "success");
+    assert_eq!(body[1]["last_result"], "refreshed 1 plans");
+    assert!(body[1]["last_duration_ms"].is_u64());
+    assert_eq!(body[1]["last_run"], "2024-05-22T10:00:00Z");
+    assert_eq!(body[2]["last_result"], "deleted 1 days before 2024-05-17");
+
+    app.mock.set_behaviour(MockBehaviour { error_rate: 1.0, ..Default::default() });
+    app.app.run_job("refresh").await;
+
+    let (_, body) = app.admin("/api/admin/jobs").await;
+    assert_eq!(body[1]["runs"], 2);
+    assert_eq!(body[1]["failures"], 1);
+    assert_eq!(body[1]["last_outcome"], "failure");
+    assert_eq!(body[1]["last_error"], "could not fetch 1 of 1 plans");
+}
+
+#[tokio::test]
+async fn job_runs_do_not_overlap() {
+    let app = app_with(noon(), Some(store_with(&stored_plan(date("2024-05-15"))).await), |_| {}).await;
+    app.mock.set_behaviour(MockBehaviour { latency: Duration::from_millis(200), ..Default::default() });
+
+    // like a manual run while the scheduled one is still going
+    let (first, second) = tokio::join!(app.app.run_job("refresh"), async {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        app.app.run_job("refresh").await
+    });
+    assert_eq!(first, JobRun::Done);
+    assert_eq!(second, JobRun::AlreadyRunning);
+    assert_eq!(app.mock.requests(), 1);
+
+    let (_, body) = app.admin("/api/admin/jobs").await;
+    assert_eq!(body[1]["name"], "refresh");
+    assert_eq!(body[1]["runs"], 1);
+    assert_eq!(body[1]["skipped"], 1);
+    assert_eq!(body[1]["running"], false);
+
+    assert_eq!(app.app.run_job("refresh").await, JobRun::Done);
+}
+
+fn prefetch_all(c: &mut Config) {
+    c.prefetch.mensas = vec!["321".into(), "322".into()];
+    c.prefetch.langs = vec!["de".into(), "en".into()];
+}
+
+#[tokio::test]
+async fn refresh_is_concurrent() {
+    let app = app_with(noon(), None, prefetch_all).await;
+    app.mock.set_behaviour(MockBehaviour {
+        latency: Duration::from_millis(300),
+        ..Default::default()
+    });
+
+    // all four are waiting on upstream at once
+    app.app.refresh().await;
+    assert_eq!(app.mock.max_concurrent(), 4);
+    assert_eq!(app.mock.requests(), 4);
+}
+
+#[tokio::test]
+async fn refresh_retries_with_backoff() {
+    let app = app_with(noon(), None, |c| {
+        c.prefetch.mensas = vec!["321".into()];
+        c.upstream.attempts = 3;
+        c.upstream.backoff_ms = 1;
+    }).await;
+    app.mock.fail_next(2);
+
+    app.app.refresh().await;
+    assert_eq!(app.mock.requests(), 3);
+
+    let (status, body) = app.admin("/api/admin/fetches").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["mensa_id"], "321");
+    assert_eq!(body[0]["attempts"], 3);
+    assert_eq!(body[0]["failures"], 0);
+    assert!(body[0]["last_success"].is_string());
+}
+
+#[tokio::test]
+async fn failed_refreshes_are_followed_up() {
+    let app = app_with(noon(), None, |c| {
+        c.prefetch.mensas = vec!["321".into()];
+        c.upstream.attempts = 1;
+        c.upstream.follow_up_secs = 0;
+        c.upstream.follow_ups = 2;
+    }).await;
+    app.mock.fail_next(2);
+
+    app.app.refresh().await;
+    let (_, body) = app.admin("/api/admin/fetches").await;
+    assert_eq!(body[0]["failures"], 1);
+    assert!(body[0]["last_error"].is_string());
+
+    // the first follow-up fails too, the second one works
+    let mut body = Value::Null;
+    for _ in 0..50 {
+        body = app.admin("/api/admin/fetches").await.1;
+        if body[0]["last_success"].is_string() { break }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(body[0]["failures"], 0);
+    assert!(body[0]["last_success"].is_string());
+    assert_eq!(app.mock.requests(), 3);
+}
+
+#[tokio::test]
+async fn malformed_mensa_ids() {
+    let app = app(noon(), None).await;
+
+    for id in ["..%2Fetc", "321%3B", "12345678901234567", "%20"] {
+        let (status, body) = app.get(&format!("/api/meals?mensa={id}")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{id}");
+        assert_eq!(body["error"]["code"], "invalid_mensa_id");
+    }
+    assert_eq!(app.mock.requests(), 0);
+}
+
+#[tokio::test]
+async fn unknown_mensas_are_cached() {
+    let app = app(noon(), None).await;
+
+    let (status, body) = app.get("/api/meals?mensa=999").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["code"], "unknown_mensa");
+    assert_eq!(app.mock.requests(), 1);
+
+    let (status, body) = app.get("/api/meals/plan?mensa=999&lang=en").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"]["code"], "unknown_mensa");
+    assert_eq!(app.mock.requests(), 1);
+    assert!(app.app.tracked().is_empty());
+
+    // known ones still work without the directory
+    let (status, _) = app.get("/api/meals?mensa=321").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.mock.requests(), 2);
+
+    // upstream is asked again once `unknown_ttl_secs` is over
+    app.advance(chrono::Duration::hours(1) + chrono::Duration::seconds(1));
+    app.get("/api/meals?mensa=999").await;
+    assert_eq!(app.mock.requests(), 3);
+}
+
+#[tokio::test]
+async fn unknown_mensas_are_per_lang() {
+    let app = app(noon(), None).await;
+
+    // the synthetic fixture only has german plans
+    let (status, _) = app.get("/api/meals/plan?mensa=900&lang=de").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.get("/api/meals/plan?mensa=900&lang=en").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(app.mock.requests(), 2);
+
+    // german is still tracked and served from memory
+    assert_eq!(app.app.tracked(), [("900".to_string(), "de".to_string())]);
+    let (status, _) = app.get("/api/meals/plan?mensa=900&lang=de").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.mock.requests(), 2);
+}
+
+#[tokio::test]
+async fn unknown_mensas_are_capped() {
+    let app = app_with(noon(), None, |c| {
+        c.cache.max_tracked = 1;
+    }).await;
+
+    app.get("/api/meals?mensa=998").await;
+    app.advance(chrono::Duration::seconds(1));
+    app.get("/api/meals?mensa=999").await;
+    assert_eq!(app.mock.requests(), 2);
+
+    // the newest is remembered, the oldest made room
+    app.get("/api/meals?mensa=999").await;
+    assert_eq!(app.mock.requests(), 2);
+    app.get("/api/meals?mensa=998").await;
+    assert_eq!(app.mock.requests(), 3);
+}
+
+#[tokio::test]
+async fn tracked_plans_are_capped() {
+    let app = app_with(noon(), None, |c| {
+        c.cache.max_tracked = 1;
+    }).await;
+
+    let (status, _) = app.get("/api/meals/plan?mensa=321").await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = app.get("/api/meals/plan?mensa=322").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(app.app.tracked(), [("321".to_string(), "en".to_string())]);
+
+    // served, but not kept
+    app.get("/api/meals/plan?mensa=322").await;
+    app.get("/api/meals/plan?mensa=321").await;
+    assert_eq!(app.mock.requests(), 3);
+}
+
+#[tokio::test]
+async fn unversioned_routes_are_deprecated_aliases() {
+    let app = app(noon(), None).await;
+
+    for uri in ["/meals?mensa=321", "/meals/plan?mensa=321", "/mensas", "/mensas/321/status"] {
+        let (status, headers, v1) = app.get_with_headers(&format!("/api/v1{uri}")).await;
+        assert_eq!(status, StatusCode::OK, "{uri}");
+        assert!(!headers.contains_key("deprecation"));
+
+        let (status, headers, old) = app.get_with_headers(&format!("/api{uri}")).await;
+        assert_eq!(status, StatusCode::OK, "{uri}");
+        assert_eq!(old, v1, "{uri}");
+        assert_eq!(headers["deprecation"], "true");
+        let path = uri.split('?').next().unwrap();
+        assert_eq!(headers["link"], format!("</api/v1{path}>; rel=\"successor-version\"").as_str());
+    }
+
+    // the v1 schema, not whatever the internal model serializes to
+    let (_, _, body) = app.get_with_headers("/api/v1/meals?mensa=321").await;
+    let meal = &body["categories"]["Main dishes"][0];
+    assert!(meal["title"].is_string());
+    assert!(meal.get("md5").is_none());
+}
+
+/// regenerate with `UPDATE_OPENAPI=1 cargo test openapi`
+#[tokio::test]
+async fn openapi_spec_is_up_to_date() {
+    let app = app(noon(), None).await;
+    let (status, spec) = app.get("/api/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+
+    for path in ["/meals", "/meals/plan", "/mensas", "/mensas/nearby", "/mensas/{id}/status"] {
+        let path = format!("/api/v1{path}");
+        assert!(spec["paths"][&path].is_object(), "{path} is not documented");
+    }
+    for name in refs(&spec) {
+        let name = name.trim_start_matches("#/components/schemas/");
+        assert!(spec["components"]["schemas"][name].is_object(), "{name} is referenced, but missing");
+    }
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/openapi.json");
+    let generated = serde_json::to_string_pretty(&spec).unwrap() + "\n";
+    if std::env::var_os("UPDATE_OPENAPI").is_some() {
+        std::fs::write(path, &generated).unwrap();
+    }
+    let committed = std::fs::read_to_string(path).unwrap_or_default();
+    assert!(committed == generated, "the openapi spec changed, run with UPDATE_OPENAPI=1 and review the diff");
+
+    let (status, body) = app.get("/api/docs").await;
+    assert_eq!(status, StatusCode::OK);
+    let body = body.as_str().unwrap();
+    assert!(body.contains("/api/openapi.json"));
+    // works offline
+    assert!(!body.contains("http://") && !body.contains("https://"));
+}
+
+/// every `$ref` in `v`
+fn refs(v: &Value) -> Vec<&str> {
+    match v {
+        Value::Object(map) => map.iter()
+            .flat_map(|(k, v)| match (k.as_str(), v) {
+                ("$ref", Value::String(v)) => vec![v.as_str()],
+                _ => refs(v),
+            })
+        .collect(),
+        Value::Array(values) => values.iter().flat_map(refs).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[tokio::test]
+async fn rendered_formats() {
+    let app = app(noon(), None).await;
+
+    let (status, headers, body) = app.get_with_headers("/api/v1/meals?mensa=321&format=text").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["content-type"], "text/plain; charset=utf-8");
+    assert_eq!(headers["vary"], "accept, accept-language");
+    let text = body.as_str().unwrap();
+    assert!(text.starts_with("Wednesday, 22.05.2024\n"), "{text}");
+    assert!(text.contains("Main dishes\n"), "{text}");
+    assert!(text.contains(" €"), "{text}");
+    assert!(text.contains("Allergens: "), "{text}");
+
+    // the text formats are cached like the json, but never match it
+    let (_, json_headers, _) = app.get_with_headers("/api/v1/meals?mensa=321").await;
+    assert!(headers.contains_key("etag") && headers.contains_key("cache-control"));
+    assert_ne!(headers["etag"], json_headers["etag"]);
+
+    let (_, headers, body) = app.get_with("/api/v1/meals?mensa=321&lang=de", &[
+        ("accept", "application/xml, text/markdown;q=0.9, */*;q=0.1"),
+    ]).await;
+    assert_eq!(headers["content-type"], "text/markdown; charset=utf-8");
+    assert!(body.as_str().unwrap().starts_with("# Mittwoch, 22.05.2024\n"));
+
+    let (_, headers, body) = app.get_with("/api/v1/meals/plan?mensa=321", &[("accept", "text/html")]).await;
+    assert_eq!(headers["content-type"], "text/html; charset=utf-8");
+    assert!(body.as_str().unwrap().starts_with("<!doctype html>"));
+
+    // prices for another group
+    let (_, _, students) = app.get_with_headers("/api/v1/meals?mensa=321&format=text").await;
+    let (_, _, guests) = app.get_with_headers("/api/v1/meals?mensa=321&format=text&price=guests").await;
+    assert_ne!(students, guests);
+
+    // `?format=` wins, unknown types get json
+    let (_, headers, body) = app.get_with("/api/v1/meals?mensa=321&format=json", &[("accept", "text/html")]).await;
+    assert_eq!(headers["content-type"], "application/json");
+    assert_eq!(body["date"], "2024-05-22");
+    let (_, headers, _) = app.get_with("/api/v1/meals?mensa=321", &[("accept", "image/png")]).await;
+    assert_eq!(headers["content-type"], "application/json");
+}
+
+#[tokio::test]
+async fn accept_language() {
+    let app = app(noon(), None).await;
+
+    let (status, headers, body) = app.get_with("/api/v1/meals/plan?mensa=321", &[
+        ("accept-language", "fr-CH, fr;q=0.9, de-DE;q=0.8, en;q=0.5"),
+    ]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["content-language"], "de");
+    assert_eq!(headers["vary"], "accept, accept-language");
+    // shares the cache entry of an explicit `lang=de`
+    let (_, _, explicit) = app.get_with_headers("/api/v1/meals/plan?mensa=321&lang=de").await;
+    assert_eq!(body, explicit);
+    assert_eq!(app.mock.requests(), 1);
+
+    // `lang` wins, nothing upstream has falls back to english
+    let (_, headers, _) = app.get_with("/api/v1/meals?mensa=321&lang=en", &[("accept-language", "de")]).await;
+    assert_eq!(headers["content-language"], "en");
+    let (_, headers, _) = app.get_with("/api/v1/meals?mensa=321", &[("accept-language", "fr, it")]).await;
+    assert_eq!(headers["content-language"], "en");
+    let (_, headers, _) = app.get_with_headers("/api/v1/meals?mensa=321").await;
+    assert_eq!(headers["content-language"], "en");
+
+    let (_, headers, _) = app.get_with("/api/v1/mensas/nearby?lat=52.45&lon=13.29&menu=true", &[
+        ("accept-language", "de"),
+    ]).await;
+    assert_eq!(headers["content-language"], "de");
+
+    for uri in ["/api/v1/mensas/321/status", "/api/v1/mensas"] {
+        let (status, headers, _) = app.get_with(uri, &[("accept-language", "de")]).await;
+        assert_eq!(status, StatusCode::OK, "{uri}");
+        assert_eq!(headers["content-language"], "de", "{uri}");
+        assert_eq!(headers["vary"], "accept-language", "{uri}");
+        let (_, headers, _) = app.get_with(&format!("{uri}?lang=en"), &[("accept-language", "de")]).await;
+        assert_eq!(headers["content-language"], "en", "{uri}");
+    }
+
+    // errors are localized too, and say so for caches
+    let (status, headers, body) = app.get_with("/api/v1/meals?mensa=321&day=someday", &[("accept-language", "de-AT")]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["message"], "Die Anfrageparameter sind ungültig.");
+    assert_eq!(headers["content-language"], "de");
+    assert_eq!(headers["vary"], "accept-language");
+    let (status, headers, body) = app.get_with("/api/v1/mensas/a-b/status", &[("accept-language", "de")]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"]["code"], "invalid_mensa_id");
+    assert_eq!(body["error"]["message"], "Mensa-IDs bestehen aus bis zu 16 Buchstaben und Ziffern.");
+    assert_eq!(headers["content-language"], "de");
+    assert_eq!(headers["vary"], "accept-language");
+    let (status, headers, _) = app.get_with("/api/v1/meals?mensa=a-b", &[("accept-language", "fr")]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(headers["content-language"], "en");
+    assert_eq!(headers["vary"], "accept-language");
+}
+
+#[tokio::test]
+async fn directory_hours_come_from_the_opening_hours() {
+    let app = app(noon(), None).await;
+
+    let (status, body) = app.get("/api/v1/mensas?q=lankwitz").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["mensas"][0]["id"], "322");
+    assert_eq!(body["mensas"][0]["opening_hours"], "Mo-Fr 11:15-14:30");
+
+    // the same hours the status is computed from
+    let (_, body) = app.get("/api/v1/mensas/322/status?at=2024-05-22T14:20:00%2B02:00").await;
+    assert_eq!(body["status"], "closing_soon");
+    assert_eq!(body["closes_at"], "2024-05-22T14:30:00+02:00");
+}
+
+#[tokio::test]
+async fn nearby_menus_are_capped() {
+    let app = app(noon(), None).await;
+
+    let (_, body) = app.get("/api/v1/mensas/nearby?lat=52.45&lon=13.29").await;
+    let all = body.as_array().unwrap().len();
+    assert!(all > 10);
+
+    let (status, body) = app.get("/api/v1/mensas/nearby?lat=52.45&lon=13.29&menu=true").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 10);
+    let (_, body) = app.get("/api/v1/mensas/nearby?lat=52.45&lon=13.29&menu=true&limit=100").await;
+    assert_eq!(body.as_array().unwrap().len(), 10);
+    let (_, body) = app.get("/api/v1/mensas/nearby?lat=52.45&lon=13.29&menu=true&limit=2").await;
+    assert_eq!(body.as_array().unwrap().len(), 2);
+    assert!(app.mock.requests() <= 12);
+}
+
+#[tokio::test]
+async fn closed_days_keep_the_next_opening() {
+    let app = app(noon(), None).await;
+
+    // pfingstmontag, the regular hours say open
+    let (status, body) = app.get("/api/v1/mensas/321/status?at=2024-05-20T12:00:00%2B02:00").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "closed");
+    assert_eq!(body["reason"], "holiday");
+    assert_eq!(body["detail"], "Pfingstmontag");
+    assert_eq!(body["opens_at"], "2024-05-21T11:00:00+02:00");
+    assert!(body.get("closes_at").is_none());
+}
+
+#[tokio::test]
+async fn next_opening_skips_holidays() {
+    let app = app(noon(), None).await;
+
+    // closed after hours, the next day is pfingstmontag
+    let (status, body) = app.get("/api/v1/mensas/321/status?at=2024-05-19T12:00:00%2B02:00").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "closed");
+    assert_eq!(body["opens_at"], "2024-05-21T11:00:00+02:00");
+
+    // thursday evening before karfreitag, then the weekend and ostermontag
+    let (_, body) = app.get("/api/v1/mensas/321/status?at=2024-03-28T18:00:00%2B01:00").await;
+    assert_eq!(body["status"], "closed");
+    assert_eq!(body["opens_at"], "2024-04-02T11:00:00+02:00");
+
+    // before opening on a regular day, it still opens the same day
+    let (_, body) = app.get("/api/v1/mensas/321/status?at=2024-05-21T09:00:00%2B02:00").await;
+    assert_eq!(body["opens_at"], "2024-05-21T11:00:00+02:00");
+}
@@ -0,0 +1,42 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use chrono_tz::{Europe::Berlin, UTC};
+use mensa_web_api::clock::Clock;
+
+fn at(s: &str) -> DateTime<Utc> { s.parse().unwrap() }
+fn date(s: &str) -> NaiveDate { s.parse().unwrap() }
+
+#[test]
+fn today_is_local() {
+    // 00:30 in berlin (summer time), still the 21st in utc
+    let now = at("2024-05-21T22:30:00Z");
+    assert_eq!(Clock::fixed(Berlin, now).today(), date("2024-05-22"));
+    assert_eq!(Clock::fixed(UTC, now).today(), date("2024-05-21"));
+
+    // only one hour ahead in winter
+    assert_eq!(Clock::fixed(Berlin, at("2024-01-01T22:30:00Z")).today(), date("2024-01-01"));
+    assert_eq!(Clock::fixed(Berlin, at("2024-01-01T23:30:00Z")).today(), date("2024-01-02"));
+}
+
+#[test]
+fn now_is_in_the_timezone() {
+    let clock = Clock::fixed(Berlin, at("2024-05-22T10:00:00Z"));
+    assert_eq!(clock.tz(), Berlin);
+    assert_eq!(clock.now().to_rfc3339(), "2024-05-22T12:00:00+02:00");
+    // the same instant, only displayed differently
+    assert_eq!(clock.now().with_timezone(&Utc), at("2024-05-22T10:00:00Z"));
+}
+
+#[test]
+fn from_fn_follows_the_source() {
+    let now = Arc::new(Mutex::new(at("2024-05-21T21:59:00Z")));
+    let clock = {
+        let now = now.clone();
+        Clock::from_fn(Berlin, move || *now.lock().unwrap())
+    };
+    assert_eq!(clock.today(), date("2024-05-21"));
+
+    *now.lock().unwrap() += Duration::minutes(2);
+    assert_eq!(clock.today(), date("2024-05-22"));
+}
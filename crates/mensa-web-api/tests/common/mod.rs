@@ -0,0 +1,152 @@
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex};
+
+use axum::{body::Body, http::{HeaderMap, Request, StatusCode}, Router};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use mensa_meal_api::{MealDay, MealPlan};
+use mensa_web_api::{
+    clock::Clock,
+    config::Config,
+    mock_upstream::MockUpstream,
+    routes::{make_app, App, Deps},
+    store::{MealStore, MemoryStore, Store},
+};
+use serde_json::Value;
+use tower::ServiceExt;
+
+pub struct TestApp {
+    pub router: Router,
+    pub app: App,
+    pub mock: MockUpstream,
+    /// what the app's clock returns, see [`TestApp::advance`]
+    pub now: Arc<Mutex<DateTime<Utc>>>,
+}
+
+/// the test app's `server.admin_token`, see [`TestApp::admin`]
+pub const ADMIN_TOKEN: &str = "test-token";
+
+/// wednesday of the first recorded week, noon in berlin
+pub fn noon() -> DateTime<Utc> {
+    "2024-05-22T10:00:00Z".parse().unwrap()
+}
+
+pub async fn app(now: DateTime<Utc>, store: Option<Store>) -> TestApp {
+    app_with(now, store, |_| {}).await
+}
+
+/// like [`app`], `f` can change the config (the upstream url and admin token are already set)
+pub async fn app_with(
+    now: DateTime<Utc>, store: Option<Store>,
+    f: impl FnOnce(&mut Config),
+) -> TestApp {
+    build(now, store, false, f).await
+}
+
+/// like [`app_with`], but fetches the `prefetch` plans at startup
+pub async fn app_prefetching(now: DateTime<Utc>, f: impl FnOnce(&mut Config)) -> TestApp {
+    build(now, None, true, f).await
+}
+
+async fn build(
+    now: DateTime<Utc>, store: Option<Store>, prefetch: bool,
+    f: impl FnOnce(&mut Config),
+) -> TestApp {
+    let mock = MockUpstream::layered([
+        concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/getdata").into(),
+        // hand written, not recorded from upstream
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/synthetic").into(),
+    ]);
+    let addr = mock.serve("127.0.0.1:0").await.unwrap();
+
+    let mut config = Config::default();
+    config.upstream.url = MockUpstream::url(addr);
+    config.server.admin_token = Some(ADMIN_TOKEN.into());
+    f(&mut config);
+
+    let now = Arc::new(Mutex::new(now));
+    let clock = {
+        let now = now.clone();
+        Clock::from_fn(config.timezone, move || *now.lock().unwrap())
+    };
+    let app = make_app(&config, Deps {
+        clock,
+        store,
+        jobs: false,
+        prefetch,
+    }).await;
+
+    TestApp { router: app.router.clone(), app, mock, now }
+}
+
+impl TestApp {
+    pub async fn get(&self, uri: &str) -> (StatusCode, Value) {
+        let (status, _, body) = self.get_with_headers(uri).await;
+        (status, body)
+    }
+
+    pub async fn get_with_headers(&self, uri: &str) -> (StatusCode, HeaderMap, Value) {
+        self.get_with(uri, &[]).await
+    }
+
+    /// a get with request headers
+    pub async fn get_with(
+        &self, uri: &str, headers: &[(&str, &str)],
+    ) -> (StatusCode, HeaderMap, Value) {
+        let mut req = Request::get(uri);
+        for (k, v) in headers {
+            req = req.header(*k, *v);
+        }
+        let res = self.router.clone()
+            .oneshot(req.body(Body::empty()).unwrap())
+        .await.unwrap();
+
+        let (status, headers) = (res.status(), res.headers().clone());
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&body)
+            .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into()));
+        (status, headers, body)
+    }
+
+    /// a get of `/api/admin` with the token
+    pub async fn admin(&self, uri: &str) -> (StatusCode, Value) {
+        let auth = format!("Bearer {ADMIN_TOKEN}");
+        let (status, _, body) = self.get_with(uri, &[("authorization", &auth)]).await;
+        (status, body)
+    }
+
+    /// moves the app's clock forward
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+/// a plan with a single day that upstream does not know about
+pub fn stored_plan(day: NaiveDate) -> MealPlan {
+    let meal: MealDay = serde_json::from_value(serde_json::json!({
+        "date": day,
+        "categories": {
+            "Essen": [{
+                "title": "Eintopf aus dem Archiv",
+                "description": null,
+                "price": null,
+                "info": {
+                    "env_rating": { "health": null, "co2": null, "h2o": null },
+                    "addatives": [], "allergens": [], "attributes": [],
+                },
+                "id": "1",
+            }],
+        },
+    })).unwrap();
+
+    let mut plan = MealPlan::new("Mensa FU II".into());
+    plan.add_day(day, meal);
+    plan
+}
+
+/// a memory store that already has `plan` for mensa 321
+pub async fn store_with(plan: &MealPlan) -> Arc<MemoryStore> {
+    let store = Arc::new(MemoryStore::default());
+    store.store_plan("321", None, plan).await.unwrap();
+    store
+}
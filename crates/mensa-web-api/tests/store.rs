@@ -0,0 +1,236 @@
+use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+
+use chrono::{DateTime, NaiveDate, Utc};
+use mensa_meal_api::{raw, MealPlan};
+use mensa_web_api::{
+    clock::Clock,
+    config::{DbConfig, DbKind},
+    store::{
+        self, MealCollections, MealStore, MemoryStore, Persistence, PersistenceState,
+        SqliteStore, StoreError, StoredDay, StoredMensa,
+    },
+};
+use mongodb::bson::{doc, oid::ObjectId, Document};
+
+fn date(s: &str) -> NaiveDate { s.parse().unwrap() }
+
+fn fixture_plan(mensa: &str, lang: &str) -> MealPlan {
+    let path = format!("{}/fixtures/getdata/{mensa}.{lang}.json", env!("CARGO_MANIFEST_DIR"));
+    let data: raw::ApiResult = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    MealPlan::try_from(data).unwrap()
+}
+
+fn fetched_at() -> DateTime<Utc> { "2024-05-22T04:00:00Z".parse().unwrap() }
+
+async fn roundtrip(store: &dyn MealStore) {
+    let mut plan = fixture_plan("321", "de");
+    plan.set_fetched("https://upstream.example", fetched_at());
+    store.store_plan("321", Some("de"), &plan).await.unwrap();
+    store.store_plan("321", None, &fixture_plan("321", "en")).await.unwrap();
+    store.store_plan("322", Some("de"), &fixture_plan("322", "de")).await.unwrap();
+    // storing twice replaces
+    store.store_plan("321", Some("de"), &plan).await.unwrap();
+
+    let day = store.get_day("321", Some("de"), &date("2024-05-22")).await.unwrap().unwrap();
+    assert_eq!(day.meal.date, date("2024-05-22"));
+    assert!(day.meal.categories.contains_key("Essen"));
+    assert_eq!(day.fetched_at, Some(fetched_at()));
+
+    // `None` is the default language
+    let day = store.get_day("321", Some("en"), &date("2024-05-22")).await.unwrap().unwrap();
+    assert!(day.meal.categories.contains_key("Main dishes"));
+    assert_eq!(day.fetched_at, None);
+
+    assert!(store.get_day("321", Some("de"), &date("2024-05-25")).await.unwrap().is_none());
+    assert!(store.get_day("999", Some("de"), &date("2024-05-22")).await.unwrap().is_none());
+
+    let range = store.get_range("321", Some("de"), &date("2024-05-22"), &date("2024-05-28")).await.unwrap();
+    let dates: Vec<_> = range.iter().map(|v| v.meal.date.to_string()).collect();
+    assert_eq!(dates, ["2024-05-22", "2024-05-23", "2024-05-24", "2024-05-27", "2024-05-28"]);
+    assert!(range.iter().all(|v| v.fetched_at == Some(fetched_at())));
+
+    let history = store.history("321", Some("de"), &date("2024-05-27"), 2).await.unwrap();
+    let dates: Vec<_> = history.iter().map(|v| v.meal.date.to_string()).collect();
+    assert_eq!(dates, ["2024-05-24", "2024-05-23"]);
+
+    let mut mensas = store.list_mensas().await.unwrap();
+    mensas.sort_by(|a, b| (&a.mensa_id, &a.lang).cmp(&(&b.mensa_id, &b.lang)));
+    assert_eq!(mensas, [
+        StoredMensa { mensa_id: "321".into(), lang: "de".into(), name: "Mensa FU II".into() },
+        StoredMensa { mensa_id: "321".into(), lang: "en".into(), name: "Mensa FU II".into() },
+        StoredMensa { mensa_id: "322".into(), lang: "de".into(), name: "Mensa FU Lankwitz".into() },
+    ]);
+
+    // 4 days of 321 (twice) and none of 322
+    assert_eq!(store.prune(&date("2024-05-27")).await.unwrap(), 8);
+    assert!(store.get_day("321", Some("de"), &date("2024-05-24")).await.unwrap().is_none());
+    assert!(store.get_day("321", Some("de"), &date("2024-05-27")).await.unwrap().is_some());
+    assert_eq!(store.prune(&date("2024-05-27")).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn memory_store() {
+    roundtrip(&MemoryStore::default()).await;
+}
+
+#[tokio::test]
+async fn sqlite_store() {
+    roundtrip(&SqliteStore::open(":memory:".as_ref()).unwrap()).await;
+}
+
+#[tokio::test]
+async fn sqlite_store_from_before_fetched_at() {
+    let path = std::env::temp_dir().join(format!("mensa-test-{}.sqlite3", std::process::id()));
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    conn.execute_batch(r#"
+        CREATE TABLE mensas (id INTEGER PRIMARY KEY, mensa_id TEXT NOT NULL, lang TEXT NOT NULL,
+            name TEXT NOT NULL, UNIQUE (mensa_id, lang));
+        CREATE TABLE meals (mensa INTEGER NOT NULL REFERENCES mensas (id), date TEXT NOT NULL,
+            meal TEXT NOT NULL, PRIMARY KEY (mensa, date));
+        INSERT INTO mensas VALUES (1, '321', 'de', 'Mensa FU II');
+        INSERT INTO meals VALUES (1, '2024-05-22', '{"date":"2024-05-22","categories":{}}');
+    "#).unwrap();
+    drop(conn);
+
+    let store = SqliteStore::open(&path).unwrap();
+    let day = store.get_day("321", Some("de"), &date("2024-05-22")).await.unwrap().unwrap();
+    assert_eq!(day.fetched_at, None);
+    // and new days get one
+    roundtrip(&store).await;
+    drop(store);
+    std::fs::remove_file(path).unwrap();
+}
+
+/// needs a running mongodb, e.g. `MENSA_TEST_MONGO_URL=mongodb://localhost:27017`
+#[tokio::test]
+async fn mongo_store() {
+    let Ok(url) = std::env::var("MENSA_TEST_MONGO_URL") else {
+        eprintln!("MENSA_TEST_MONGO_URL is not set, skipping");
+        return
+    };
+    let client = mongodb::Client::with_uri_str(&url).await.unwrap();
+    let db = client.database(&format!("mensa_test_{}", ObjectId::new()));
+
+    // duplicates from before the unique indexes
+    let mensas = db.collection::<Document>("mensas");
+    let meals = db.collection::<Document>("meals");
+    let (old, new) = (ObjectId::new(), ObjectId::new());
+    for id in [old, new] {
+        mensas.insert_one(doc! { "_id": id, "mensa_id": "321", "lang": "de", "name": "Mensa FU II" }, None).await.unwrap();
+    }
+    for id in [old, new, new] {
+        let meal = doc! { "date": "2024-05-22", "categories": {}, "state": { "kind": "open" } };
+        meals.insert_one(doc! { "mensa_record_id": id, "meal": meal }, None).await.unwrap();
+    }
+
+    let collections = MealCollections::new(&client, &db);
+    collections.init().await.unwrap();
+    assert_eq!(mensas.count_documents(None, None).await.unwrap(), 1);
+    assert_eq!(meals.count_documents(doc! { "mensa_record_id": new }, None).await.unwrap(), 1);
+    // and again, with the indexes in place
+    collections.init().await.unwrap();
+
+    mensas.delete_many(doc! {}, None).await.unwrap();
+    meals.delete_many(doc! {}, None).await.unwrap();
+    roundtrip(&collections).await;
+    db.drop(None).await.unwrap();
+}
+
+#[tokio::test]
+async fn unreachable_mongo_is_no_store() {
+    let config = DbConfig {
+        kind: DbKind::Mongo,
+        url: "mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=100".into(),
+        ..DbConfig::default()
+    };
+    // the migrations ran (and failed) before this returns
+    assert!(store::connect(&config).await.is_none());
+}
+
+/// a memory store whose writes fail while `down` is set
+#[derive(Default)]
+struct FlakyStore {
+    inner: MemoryStore,
+    down: AtomicBool,
+}
+
+#[async_trait::async_trait]
+impl MealStore for FlakyStore {
+    async fn store_plan(
+        &self, mensa_id: &str, lang: Option<&str>, plan: &MealPlan,
+    ) -> Result<(), StoreError> {
+        if self.down.load(Ordering::SeqCst) {
+            let failed = plan.days().map(|v| v.date).collect();
+            return Err(StoreError::Partial { failed, total: plan.days().len() })
+        }
+        self.inner.store_plan(mensa_id, lang, plan).await
+    }
+
+    async fn get_day(
+        &self, mensa_id: &str, lang: Option<&str>, day: &NaiveDate,
+    ) -> Result<Option<StoredDay>, StoreError> {
+        self.inner.get_day(mensa_id, lang, day).await
+    }
+
+    async fn get_range(
+        &self, mensa_id: &str, lang: Option<&str>, from: &NaiveDate, to: &NaiveDate,
+    ) -> Result<Vec<StoredDay>, StoreError> {
+        self.inner.get_range(mensa_id, lang, from, to).await
+    }
+
+    async fn history(
+        &self, mensa_id: &str, lang: Option<&str>, before: &NaiveDate, limit: usize,
+    ) -> Result<Vec<StoredDay>, StoreError> {
+        self.inner.history(mensa_id, lang, before, limit).await
+    }
+
+    async fn list_mensas(&self) -> Result<Vec<StoredMensa>, StoreError> {
+        self.inner.list_mensas().await
+    }
+
+    async fn prune(&self, before: &NaiveDate) -> Result<usize, StoreError> {
+        self.inner.prune(before).await
+    }
+}
+
+#[tokio::test]
+async fn failed_writes_are_retried() {
+    let store = Arc::new(FlakyStore::default());
+    let now: DateTime<Utc> = "2024-05-22T10:00:00Z".parse().unwrap();
+    let persistence = Persistence::new(store.clone(), Clock::fixed(chrono_tz::Europe::Berlin, now));
+    store.down.store(true, Ordering::SeqCst);
+
+    // the first week, then a plan that only has the second one
+    let full = fixture_plan("321", "de");
+    let mut first = full.clone();
+    let mut second = full.clone();
+    for day in full.days() {
+        if day.date < date("2024-05-27") { second.remove_day(day.date) } else { first.remove_day(day.date) }
+    }
+
+    persistence.write("321", Some("de"), first).await;
+    persistence.write("321", Some("de"), second).await;
+
+    let [status] = &persistence.statuses()[..] else { panic!("expected a single status") };
+    assert_eq!(status.state, PersistenceState::Pending);
+    assert_eq!(status.pending_days, 9);
+    assert_eq!(status.failures, 2);
+    assert!(status.last_error.is_some());
+    assert_eq!(persistence.pending_days(), 9);
+
+    // still down, nothing lost
+    persistence.retry().await;
+    assert_eq!(persistence.pending_days(), 9);
+
+    store.down.store(false, Ordering::SeqCst);
+    persistence.retry().await;
+
+    let [status] = &persistence.statuses()[..] else { panic!("expected a single status") };
+    assert_eq!(status.state, PersistenceState::Stored);
+    assert_eq!(status.failures, 0);
+    assert_eq!(status.last_stored, Some(now));
+    assert_eq!(persistence.pending_days(), 0);
+
+    let stored = store.get_range("321", Some("de"), &date("2024-05-01"), &date("2024-06-30")).await.unwrap();
+    assert_eq!(stored.len(), 9);
+}